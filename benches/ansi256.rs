@@ -0,0 +1,40 @@
+//! Time mapping a banner's worth of gradient colors to the 256-color
+//! palette, with and without the cache.
+//!
+//! Run with `cargo bench --bench ansi256`.
+
+use banner::color::{nearest_ansi256, Ansi256};
+use std::hint::black_box;
+use std::time::Instant;
+
+fn main() {
+    // A horizontal gradient repeated down 40 rows of 200 columns, the way a
+    // smooth banner repeats its colors
+    let colors: Vec<(u8, u8, u8)> = (0..40)
+        .flat_map(|_| {
+            (0..200u32).map(|x| ((x * 255 / 199) as u8, 128, (255 - x * 255 / 199) as u8))
+        })
+        .collect();
+    let rounds = 50;
+
+    let start = Instant::now();
+    for _ in 0..rounds {
+        for &color in &colors {
+            black_box(nearest_ansi256(black_box(color)));
+        }
+    }
+    let uncached = start.elapsed();
+
+    let start = Instant::now();
+    for _ in 0..rounds {
+        let mut cache = Ansi256::new();
+        for &color in &colors {
+            black_box(cache.index(black_box(color)));
+        }
+    }
+    let cached = start.elapsed();
+
+    let per_banner = |total: std::time::Duration| total / rounds;
+    println!("uncached: {:?} per banner", per_banner(uncached));
+    println!("cached:   {:?} per banner", per_banner(cached));
+}
@@ -0,0 +1,503 @@
+use clap::ValueEnum;
+use palette::LinSrgb;
+use std::collections::{HashMap, HashSet};
+use std::env;
+use std::fmt;
+
+/// How colors are encoded in the emitted escape sequences
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum ColorMode {
+    /// Pick based on what the terminal advertises
+    Auto,
+    /// 24-bit RGB escapes
+    Truecolor,
+    /// The xterm 256-color palette
+    #[value(name = "256")]
+    Ansi256,
+}
+
+impl ColorMode {
+    /// Replace `Auto` with the best mode the terminal supports.
+    pub fn resolve(self) -> Self {
+        match self {
+            ColorMode::Auto => Capability::detect().recommended_mode(),
+            mode => mode,
+        }
+    }
+}
+
+/// Color support advertised by the terminal environment
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Capability {
+    Truecolor,
+    Ansi256,
+    Monochrome,
+}
+
+impl Capability {
+    /// Detect color support from `$COLORTERM` and `$TERM`.
+    pub fn detect() -> Self {
+        let colorterm = env::var("COLORTERM").unwrap_or_default();
+        let term = env::var("TERM").unwrap_or_default();
+
+        if colorterm == "truecolor" || colorterm == "24bit" || term.ends_with("-direct") {
+            Capability::Truecolor
+        } else if term.contains("256color") {
+            Capability::Ansi256
+        } else {
+            Capability::Monochrome
+        }
+    }
+
+    /// The color mode to use for this capability.
+    ///
+    /// There is no monochrome output, so those terminals get the mode most
+    /// likely to degrade gracefully.
+    pub fn recommended_mode(self) -> ColorMode {
+        match self {
+            Capability::Truecolor => ColorMode::Truecolor,
+            Capability::Ansi256 | Capability::Monochrome => ColorMode::Ansi256,
+        }
+    }
+}
+
+impl fmt::Display for Capability {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Capability::Truecolor => write!(f, "truecolor"),
+            Capability::Ansi256 => write!(f, "256 colors"),
+            Capability::Monochrome => write!(f, "monochrome"),
+        }
+    }
+}
+
+/// How colors outside the displayable range are brought back into it
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum Gamut {
+    /// Clamp each channel on its own
+    Clip,
+    /// Pull the color toward gray until it fits, keeping its hue
+    Compress,
+}
+
+/// Kind of color vision deficiency to simulate or correct for
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum ColorBlindness {
+    /// No working red cones
+    Protanopia,
+    /// No working green cones
+    Deuteranopia,
+    /// No working blue cones
+    Tritanopia,
+}
+
+impl ColorBlindness {
+    /// Linear RGB transform simulating the deficiency at full severity, from
+    /// Machado, Oliveira and Fernandes (2009).
+    fn matrix(self) -> [[f64; 3]; 3] {
+        match self {
+            ColorBlindness::Protanopia => [
+                [0.152286, 1.052583, -0.204868],
+                [0.114503, 0.786281, 0.099216],
+                [-0.003882, -0.048116, 1.051998],
+            ],
+            ColorBlindness::Deuteranopia => [
+                [0.367322, 0.860646, -0.227968],
+                [0.280085, 0.672501, 0.047413],
+                [-0.011820, 0.042940, 0.968881],
+            ],
+            ColorBlindness::Tritanopia => [
+                [1.255528, -0.076749, -0.178779],
+                [-0.078411, 0.930809, 0.147602],
+                [0.004733, 0.691367, 0.303900],
+            ],
+        }
+    }
+
+    /// How `rgb` looks with this deficiency.
+    ///
+    /// With `correct`, the color is instead daltonized: the detail the
+    /// deficiency loses is shifted into channels that are still seen, so
+    /// colors that would look the same stay apart.
+    pub fn apply(self, (r, g, b): (u8, u8, u8), correct: bool) -> (u8, u8, u8) {
+        let source = [linear(r), linear(g), linear(b)];
+        let m = self.matrix();
+        let seen: Vec<f64> = m
+            .iter()
+            .map(|row| row[0] * source[0] + row[1] * source[1] + row[2] * source[2])
+            .collect();
+        if !correct {
+            return (encode(seen[0]), encode(seen[1]), encode(seen[2]));
+        }
+
+        // Fidaner et al.: the error is what the deficiency loses in each
+        // channel. Red-green losses are moved into green and blue, while the
+        // blue-yellow loss of tritanopia is moved into red and green instead
+        let error: Vec<f64> = source.iter().zip(&seen).map(|(s, v)| s - v).collect();
+        match self {
+            ColorBlindness::Protanopia | ColorBlindness::Deuteranopia => (
+                encode(source[0]),
+                encode(source[1] + 0.7 * error[0] + error[1]),
+                encode(source[2] + 0.7 * error[0] + error[2]),
+            ),
+            ColorBlindness::Tritanopia => (
+                encode(source[0] + error[0] + 0.7 * error[2]),
+                encode(source[1] + error[1] + 0.7 * error[2]),
+                encode(source[2]),
+            ),
+        }
+    }
+}
+
+/// Convert a gradient color, with channels nominally in [0, 1], to 8 bits.
+///
+/// Channels are truncated rather than rounded, matching how banners have
+/// always been colored, and never wrap around.
+pub fn to_rgb(color: LinSrgb<f64>, gamut: Gamut) -> (u8, u8, u8) {
+    let (mut r, mut g, mut b) = (color.red, color.green, color.blue);
+    if gamut == Gamut::Compress {
+        // Shrink the distance to the mean until the furthest channel is back
+        // at the edge of [0, 1]
+        let gray = ((r + g + b) / 3.0).clamp(0.0, 1.0);
+        let fit = [r, g, b]
+            .iter()
+            .map(|&c| match c {
+                c if c > 1.0 => (1.0 - gray) / (c - gray),
+                c if c < 0.0 => gray / (gray - c),
+                _ => 1.0,
+            })
+            .fold(1.0, f64::min);
+        (r, g, b) = (
+            gray + (r - gray) * fit,
+            gray + (g - gray) * fit,
+            gray + (b - gray) * fit,
+        );
+    }
+    let channel = |c: f64| {
+        if c.is_nan() {
+            0
+        } else {
+            (c.clamp(0.0, 1.0) * 255.0) as u8
+        }
+    };
+    (channel(r), channel(g), channel(b))
+}
+
+/// Whether the terminal is known to display Sixel graphics.
+///
+/// Terminals don't advertise Sixel in the environment, so this only
+/// recognizes ones that identify themselves and always support it.
+pub fn sixel_supported() -> bool {
+    let term = env::var("TERM").unwrap_or_default();
+    let program = env::var("TERM_PROGRAM").unwrap_or_default();
+    term.contains("sixel")
+        || ["mlterm", "foot", "yaft", "contour"]
+            .iter()
+            .any(|name| term.starts_with(name))
+        || program == "WezTerm"
+}
+
+/// Whether the terminal is known to display Kitty graphics.
+pub fn kitty_supported() -> bool {
+    let term = env::var("TERM").unwrap_or_default();
+    let program = env::var("TERM_PROGRAM").unwrap_or_default();
+    env::var_os("KITTY_WINDOW_ID").is_some() || term == "xterm-kitty" || program == "WezTerm"
+}
+
+/// Parse a `#rrggbb` (or `rrggbb`) hex color.
+pub fn parse_hex_color(s: &str) -> Result<(u8, u8, u8), String> {
+    let hex = s.strip_prefix('#').unwrap_or(s);
+    if hex.len() != 6 || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(format!("invalid color {:?}, expected #rrggbb", s));
+    }
+    let channel = |k: usize| u8::from_str_radix(&hex[k..k + 2], 16).unwrap();
+    Ok((channel(0), channel(2), channel(4)))
+}
+
+/// Decode an 8-bit sRGB channel to linear light in [0, 1].
+fn linear(c: u8) -> f64 {
+    let c = c as f64 / 255.0;
+    if c <= 0.03928 {
+        c / 12.92
+    } else {
+        ((c + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+/// Encode linear light back to an 8-bit sRGB channel, clamping to [0, 1].
+fn encode(c: f64) -> u8 {
+    let c = c.clamp(0.0, 1.0);
+    let c = if c <= 0.03928 / 12.92 {
+        c * 12.92
+    } else {
+        1.055 * c.powf(1.0 / 2.4) - 0.055
+    };
+    (c * 255.0).round() as u8
+}
+
+/// WCAG relative luminance of an sRGB color, from 0 (black) to 1 (white).
+pub fn relative_luminance((r, g, b): (u8, u8, u8)) -> f64 {
+    0.2126 * linear(r) + 0.7152 * linear(g) + 0.0722 * linear(b)
+}
+
+/// Mix `rgb` toward white just enough to reach at least `target` relative
+/// luminance. Colors that are already bright enough are returned unchanged.
+pub fn lift_to_luminance(rgb: (u8, u8, u8), target: f64) -> (u8, u8, u8) {
+    let mix = |t: f64| {
+        let channel = |c: u8| (c as f64 + (255.0 - c as f64) * t).round() as u8;
+        (channel(rgb.0), channel(rgb.1), channel(rgb.2))
+    };
+    if relative_luminance(rgb) >= target {
+        return rgb;
+    }
+
+    // Luminance grows monotonically with the mix, so bisect for the boundary
+    let (mut lo, mut hi) = (0.0, 1.0);
+    for _ in 0..16 {
+        let mid = (lo + hi) / 2.0;
+        if relative_luminance(mix(mid)) >= target {
+            hi = mid;
+        } else {
+            lo = mid;
+        }
+    }
+    mix(hi)
+}
+
+/// Channel levels of the 6x6x6 color cube at palette indices 16..=231
+const CUBE_LEVELS: [u8; 6] = [0, 95, 135, 175, 215, 255];
+
+/// Maps RGB colors to their nearest xterm 256-color palette entry.
+///
+/// Finding the nearest entry means scanning the whole palette, which adds up
+/// over a full banner. Smooth gradients repeat the same colors many times,
+/// so results are memoized per color and match [`nearest_ansi256`] exactly.
+pub struct Ansi256 {
+    cache: HashMap<(u8, u8, u8), u8>,
+}
+
+impl Ansi256 {
+    pub fn new() -> Self {
+        Self {
+            cache: HashMap::new(),
+        }
+    }
+
+    /// The palette index closest to `color`.
+    pub fn index(&mut self, color: (u8, u8, u8)) -> u8 {
+        *self
+            .cache
+            .entry(color)
+            .or_insert_with(|| nearest_ansi256(color))
+    }
+}
+
+impl Default for Ansi256 {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The RGB value of palette index `index`, for indices 16 and above.
+///
+/// The first 16 entries are left out since terminals theme them freely.
+pub(crate) fn ansi256_rgb(index: u8) -> (u8, u8, u8) {
+    if index >= 232 {
+        let level = 8 + 10 * (index - 232);
+        (level, level, level)
+    } else {
+        let i = (index - 16) as usize;
+        (
+            CUBE_LEVELS[i / 36],
+            CUBE_LEVELS[(i / 6) % 6],
+            CUBE_LEVELS[i % 6],
+        )
+    }
+}
+
+/// Exhaustively search the palette for the entry closest to `(r, g, b)`.
+pub fn nearest_ansi256((r, g, b): (u8, u8, u8)) -> u8 {
+    (16..=255)
+        .min_by_key(|&index| {
+            let (pr, pg, pb) = ansi256_rgb(index);
+            let dr = r as i32 - pr as i32;
+            let dg = g as i32 - pg as i32;
+            let db = b as i32 - pb as i32;
+            dr * dr + dg * dg + db * db
+        })
+        .unwrap()
+}
+
+/// Pick a palette of at most `n` colors for `colors` by median cut.
+///
+/// The colors start out in one box, and the box spanning the widest range
+/// on any channel is split at its median along that channel until there are
+/// `n` boxes, or none left to split. Each box gives the mean of its colors,
+/// so colors used more often pull the palette harder.
+pub(crate) fn median_cut(colors: &[(u8, u8, u8)], n: usize) -> Vec<(u8, u8, u8)> {
+    let channel = |c: (u8, u8, u8), k: usize| [c.0, c.1, c.2][k];
+    // The widest channel of a box and how wide it is
+    let widest = |colors: &[(u8, u8, u8)]| {
+        (0..3)
+            .map(|k| {
+                let values = colors.iter().map(|&c| channel(c, k));
+                let range = values.clone().max().unwrap_or(0) - values.min().unwrap_or(0);
+                (k, range)
+            })
+            .max_by_key(|&(_, range)| range)
+            .unwrap_or((0, 0))
+    };
+
+    let mut boxes = vec![colors.to_vec()];
+    while boxes.len() < n {
+        let Some((index, (k, _))) = boxes
+            .iter()
+            .map(|colors| widest(colors))
+            .enumerate()
+            .filter(|&(_, (_, range))| range > 0)
+            .max_by_key(|&(_, (_, range))| range)
+        else {
+            break;
+        };
+        let mut colors = boxes.swap_remove(index);
+        colors.sort_unstable_by_key(|&c| channel(c, k));
+        let upper = colors.split_off(colors.len() / 2);
+        boxes.push(colors);
+        boxes.push(upper);
+    }
+
+    let mut palette: Vec<(u8, u8, u8)> = Vec::new();
+    for colors in boxes.iter().filter(|colors| !colors.is_empty()) {
+        let n = colors.len() as f64;
+        let mean = |k| (colors.iter().map(|&c| channel(c, k) as f64).sum::<f64>() / n).round();
+        let color = (mean(0) as u8, mean(1) as u8, mean(2) as u8);
+        // Boxes split from a run of one color can average the same
+        if !palette.contains(&color) {
+            palette.push(color);
+        }
+    }
+    palette
+}
+
+/// The entry of `palette` closest to `(r, g, b)`.
+pub(crate) fn nearest((r, g, b): (f64, f64, f64), palette: &[(u8, u8, u8)]) -> (u8, u8, u8) {
+    let distance = |&(pr, pg, pb): &(u8, u8, u8)| {
+        (r - pr as f64).powi(2) + (g - pg as f64).powi(2) + (b - pb as f64).powi(2)
+    };
+    palette
+        .iter()
+        .copied()
+        .min_by(|a, b| distance(a).total_cmp(&distance(b)))
+        .unwrap_or((0, 0, 0))
+}
+
+/// Writes SGR color sequences in the selected color mode.
+pub struct Painter {
+    mode: ColorMode,
+    ansi256: Ansi256,
+    used: HashSet<(u8, u8, u8)>,
+}
+
+impl Painter {
+    pub fn new(mode: ColorMode) -> Self {
+        Self {
+            mode: mode.resolve(),
+            ansi256: Ansi256::new(),
+            used: HashSet::new(),
+        }
+    }
+
+    /// Number of distinct colors emitted so far, after any palette mapping.
+    pub fn color_count(&self) -> usize {
+        self.used.len()
+    }
+
+    /// Escape sequence setting the background to `rgb`.
+    pub fn bg(&mut self, rgb: (u8, u8, u8)) -> String {
+        self.sgr(48, rgb)
+    }
+
+    /// Escape sequence setting the foreground to `rgb`.
+    pub fn fg(&mut self, rgb: (u8, u8, u8)) -> String {
+        self.sgr(38, rgb)
+    }
+
+    fn sgr(&mut self, layer: u8, rgb: (u8, u8, u8)) -> String {
+        match self.mode {
+            ColorMode::Auto | ColorMode::Truecolor => {
+                self.used.insert(rgb);
+                format!("\x1b[{};2;{};{};{}m", layer, rgb.0, rgb.1, rgb.2)
+            }
+            ColorMode::Ansi256 => {
+                let index = self.ansi256.index(rgb);
+                self.used.insert(ansi256_rgb(index));
+                format!("\x1b[{};5;{}m", layer, index)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Hue angle of a color, in degrees
+    fn hue((r, g, b): (f64, f64, f64)) -> f64 {
+        (3f64.sqrt() * (g - b)).atan2(2.0 * r - g - b).to_degrees()
+    }
+
+    fn unit((r, g, b): (u8, u8, u8)) -> (f64, f64, f64) {
+        (r as f64 / 255.0, g as f64 / 255.0, b as f64 / 255.0)
+    }
+
+    #[test]
+    fn clip_clamps_out_of_range_channels() {
+        let clip = |r, g, b| to_rgb(LinSrgb::new(r, g, b), Gamut::Clip);
+        assert_eq!(clip(-0.5, 1.5, 0.25), (0, 255, 63));
+        assert_eq!(clip(1e9, -1e9, 2.0), (255, 0, 255));
+        assert_eq!(clip(f64::NAN, 0.0, 1.0), (0, 0, 255));
+    }
+
+    #[test]
+    fn compress_keeps_hue_and_moves_toward_gray() {
+        for (r, g, b) in [(1.4, 0.8, 0.2), (-0.3, 0.4, 0.8), (1.2, -0.1, 0.5)] {
+            let out = unit(to_rgb(LinSrgb::new(r, g, b), Gamut::Compress));
+            let (spread_in, spread_out) = (
+                r.max(g).max(b) - r.min(g).min(b),
+                out.0.max(out.1).max(out.2) - out.0.min(out.1).min(out.2),
+            );
+            assert!(spread_out < spread_in, "{:?} gave {:?}", (r, g, b), out);
+            let turn = (hue(out) - hue((r, g, b))).abs();
+            assert!(turn < 2.0, "{:?} gave {:?}", (r, g, b), out);
+        }
+    }
+
+    #[test]
+    fn tritan_correction_differs_from_protan_on_blue() {
+        let blue = (40, 60, 220);
+        let tritan = ColorBlindness::Tritanopia.apply(blue, true);
+        let protan = ColorBlindness::Protanopia.apply(blue, true);
+        assert_ne!(tritan, protan);
+        // Tritan correction leaves blue alone and moves its loss elsewhere
+        assert_eq!(tritan.2, blue.2);
+        assert_ne!((tritan.0, tritan.1), (blue.0, blue.1));
+    }
+
+    #[test]
+    fn ansi256_cache_matches_an_uncached_search() {
+        // Colors spread over the cube, each looked up twice so the second
+        // comes from the cache
+        let mut cache = Ansi256::new();
+        let colors: Vec<_> = (0..400u32)
+            .map(|i| {
+                let x = i.wrapping_mul(2_654_435_761);
+                (x as u8, (x >> 8) as u8, (x >> 16) as u8)
+            })
+            .collect();
+        for _ in 0..2 {
+            for &color in &colors {
+                assert_eq!(cache.index(color), nearest_ansi256(color), "{:?}", color);
+            }
+        }
+    }
+}
@@ -0,0 +1,912 @@
+use crate::color::{parse_hex_color, ColorBlindness, ColorMode, Gamut};
+use crate::profile::DEFAULT_PROFILE_FILE;
+use crate::theme::DEFAULT_THEME;
+use clap::{ArgGroup, Parser, ValueEnum};
+use std::path::PathBuf;
+use unicode_segmentation::UnicodeSegmentation;
+
+/// Generate an ASCII heatmap with Perlin noise
+#[derive(Parser, Clone, Debug)]
+#[command(author, version, about, long_about = None)]
+#[command(group(ArgGroup::new("overlay").args(["text", "text_file", "symbol", "text_sequence", "stdin_lines"])))]
+pub struct HeatmapConfig {
+    /// Number of rows
+    #[arg(required_unless_present_any = ["from_grid", "probe", "palette_preview", "list_themes", "measure_text", "list_profiles", "reproduce"])]
+    pub rows: Option<usize>,
+
+    /// Number of columns [default: terminal width, then $COLUMNS, then 80]
+    #[arg(short, long)]
+    pub width: Option<usize>,
+
+    /// Scale
+    #[arg(short, long, default_value_t = 100.0)]
+    pub scale: f64,
+
+    /// Octaves
+    #[arg(short, long, default_value_t = 6)]
+    pub octaves: usize,
+
+    /// Pick the octave count from the scale and lacunarity, dropping octaves
+    /// too fine for the grid to show
+    #[arg(long, conflicts_with = "octaves")]
+    pub auto_octaves: bool,
+
+    /// Horizontal offset into the noise field, in cells
+    #[arg(long, default_value_t = 0.0, allow_negative_numbers = true, value_parser = parse_noise_offset)]
+    pub offset_x: f64,
+
+    /// Vertical offset into the noise field, in cells
+    #[arg(long, default_value_t = 0.0, allow_negative_numbers = true, value_parser = parse_noise_offset)]
+    pub offset_y: f64,
+
+    /// Move the offsets so the brightest point of the banner's noise sits
+    /// behind the middle of the text
+    #[arg(long, requires = "overlay", conflicts_with_all = ["rotate", "transpose", "from_grid", "repeat_noise"])]
+    pub center_on_text: bool,
+
+    /// Rotate the noise field around its center, in degrees
+    #[arg(long, default_value_t = 0.0, allow_negative_numbers = true, value_parser = parse_finite)]
+    pub rotate: f64,
+
+    /// Sample the noise with rows and columns swapped, turning the field on
+    /// its side while the banner keeps its size
+    #[arg(long)]
+    pub transpose: bool,
+
+    /// Noise generator behind the heatmap
+    #[arg(long, value_enum, default_value_t = NoiseKind::Perlin)]
+    pub noise: NoiseKind,
+
+    /// Noise generator for each octave in turn, e.g. "perlin,perlin,worley",
+    /// starting over when there are more octaves than names. Slower than a
+    /// single --noise, which is specialized for its generator
+    #[arg(long, value_name = "LIST", value_enum, value_delimiter = ',', conflicts_with_all = ["noise", "expr", "from_grid", "field"])]
+    pub octave_noise: Option<Vec<NoiseKind>>,
+
+    /// Function behind the heatmap: --noise, or a plain geometric pattern.
+    /// Sine waves and checker squares are --scale cells across
+    #[arg(long, value_enum, default_value_t = FieldKind::Perlin, conflicts_with_all = ["expr", "from_grid"])]
+    pub field: FieldKind,
+
+    /// Experimental: reverse the gradient where an odd octave outweighs the
+    /// others, so coarse and fine features get different hues. Slower, since
+    /// every cell is sampled a second time to find its strongest octave
+    #[arg(long, conflicts_with_all = ["expr", "from_grid", "field", "repeat_noise"])]
+    pub palette_reverse_per_octave: bool,
+
+    /// Combine noise sources with an expression, e.g. "0.6*perlin(s=100) + 0.4*worley(s=40)".
+    ///
+    /// Supports + - * /, parentheses, perlin(s, o, p, l, f, seed) and worley(s, seed).
+    /// Parameters left out fall back to the matching flags.
+    #[arg(
+        long,
+        value_name = "EXPR",
+        allow_hyphen_values = true,
+        conflicts_with = "from_grid"
+    )]
+    pub expr: Option<String>,
+
+    /// Sample the noise on a grid N times coarser and scale it up, for quick
+    /// but blockier renders
+    #[arg(long, value_name = "N", default_value_t = 1, value_parser = clap::value_parser!(u64).range(1..))]
+    pub downsample: u64,
+
+    /// How --downsample and image exports scale the grid up: nearest keeps
+    /// hard cell edges, bilinear and lanczos blend neighbouring cells
+    #[arg(long, value_enum, default_value_t = Resample::Nearest)]
+    pub resample_filter: Resample,
+
+    /// Generate an N x N patch of noise and tile it across the banner
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(u64).range(1..))]
+    pub repeat_noise: Option<u64>,
+
+    /// Persistence
+    #[arg(short, long, default_value_t = 0.5)]
+    pub persistence: f64,
+
+    /// Lacunarity
+    #[arg(short, long, default_value_t = 2.0)]
+    pub lacunarity: f64,
+
+    /// Extra amplitude falloff for the finest octaves, from 0 (none) to 1
+    #[arg(long, value_name = "F", default_value_t = 0.0, value_parser = parse_falloff)]
+    pub detail_falloff: f64,
+
+    /// Shift octave k's sample coordinates by k times this, so octaves
+    /// sharing an origin don't line their features up along the grid. 0
+    /// leaves every octave on the same origin
+    #[arg(long, value_name = "D", default_value_t = 0.0, allow_negative_numbers = true, value_parser = parse_finite)]
+    pub octave_offset: f64,
+
+    /// Fade factor range
+    #[arg(short, long, default_value_t = 0.1)]
+    pub fade_factor_range: f64,
+
+    /// Darken the heatmap toward this edge, or radially
+    #[arg(long, value_enum)]
+    pub fade_direction: Option<FadeDirection>,
+
+    /// Darken both side edges, leaving a bright band down the middle
+    #[arg(long, conflicts_with = "fade_direction")]
+    pub bidirectional_fade: bool,
+
+    /// Steepness of --bidirectional-fade. Above 1 the middle stays bright
+    /// for longer and the edges darken more sharply
+    #[arg(long, value_name = "S", default_value_t = 1.0, requires = "bidirectional_fade", value_parser = parse_steepness)]
+    pub fade_steepness: f64,
+
+    /// Random seed for the noise [default: the noise's fixed built-in seed]
+    #[arg(short, long)]
+    pub random: Option<u64>,
+
+    /// Seed from the Unix time divided by SECONDS, so the banner changes once
+    /// per window but is reproducible within it
+    #[arg(
+        long,
+        value_name = "SECONDS",
+        num_args = 0..=1,
+        default_missing_value = "60",
+        value_parser = clap::value_parser!(u64).range(1..),
+        conflicts_with_all = ["random", "seed_sequence"]
+    )]
+    pub seed_from_time: Option<u64>,
+
+    /// Render one banner per line of stdin, using the line as its text
+    #[arg(long, conflicts_with_all = ["text", "animate", "palette_preview"])]
+    pub stdin_lines: bool,
+
+    /// Seed the Nth --stdin-lines banner with BASE + N, overriding --random
+    #[arg(long, value_name = "BASE", requires = "stdin_lines")]
+    pub seed_sequence: Option<u64>,
+
+    /// Text to overlay
+    #[arg(short, long)]
+    pub text: Option<String>,
+
+    /// Stretch the text by putting N blank rows between the rows of its
+    /// letters
+    #[arg(long, value_name = "N", default_value_t = 0, requires = "overlay")]
+    pub line_height: usize,
+
+    /// Put each word of the text on its own row of letters
+    #[arg(long, requires = "overlay")]
+    pub word_per_line: bool,
+
+    /// Read the text to overlay from a file, one row of letters per line
+    #[arg(long, value_name = "PATH", conflicts_with_all = ["text", "stdin_lines"])]
+    pub text_file: Option<PathBuf>,
+
+    /// Overlay one large symbol, such as ♥ or ★, instead of text
+    #[arg(long, value_name = "CHAR", value_parser = parse_grapheme, conflicts_with_all = ["text", "text_file", "stdin_lines"])]
+    pub symbol: Option<String>,
+
+    /// Cycle the overlay through these texts while animating, each centered
+    /// on the widest
+    #[arg(
+        long,
+        value_name = "LIST",
+        value_delimiter = ',',
+        requires = "animate",
+        conflicts_with = "stdin_lines"
+    )]
+    pub text_sequence: Option<Vec<String>>,
+
+    /// Frames each --text-sequence text is shown for
+    #[arg(long, value_name = "FRAMES", default_value_t = 40, requires = "text_sequence",
+          value_parser = clap::value_parser!(u64).range(1..))]
+    pub text_hold: u64,
+
+    /// Type the overlay out left to right while animating, a few columns of
+    /// letters more each frame
+    #[arg(long, requires = "animate")]
+    pub animate_text_reveal: bool,
+
+    /// Columns of letters --animate-text-reveal uncovers each frame
+    #[arg(long, value_name = "COLUMNS", default_value_t = 1, requires = "animate_text_reveal",
+          value_parser = clap::value_parser!(u64).range(1..))]
+    pub reveal_speed: u64,
+
+    /// Render a 2D grid of values from a JSON or CSV file instead of noise
+    #[arg(long, value_name = "PATH")]
+    pub from_grid: Option<PathBuf>,
+
+    /// Scale the field by the brightness of a PNG or JPEG image stretched
+    /// over it, so the texture takes the image's shape
+    #[arg(long, value_name = "PATH")]
+    pub noise_mask: Option<PathBuf>,
+
+    /// Place the text vertically within all the rows, instead of cropping
+    /// the banner to the text
+    #[arg(long, value_enum, requires = "overlay")]
+    pub text_valign: Option<VerticalAlign>,
+
+    /// Blank heatmap columns to insert between letters
+    #[arg(long, value_name = "N", default_value_t = 0, requires = "overlay")]
+    pub letter_spacing: usize,
+
+    /// Spaces to add on both sides of each line of text before drawing it
+    #[arg(
+        long,
+        value_name = "N",
+        default_value_t = 0,
+        requires = "overlay",
+        conflicts_with = "symbol"
+    )]
+    pub pad_text: usize,
+
+    /// What to do with text taller than the banner
+    #[arg(long, value_enum, default_value_t = Overflow::CropBottom)]
+    pub overflow: Overflow,
+
+    /// Smooth letter edges with partial block characters
+    #[arg(long)]
+    pub smooth_text: bool,
+
+    /// Color each letter with its own step along the gradient
+    #[arg(long, requires = "overlay")]
+    pub rainbow_text: bool,
+
+    /// Darken the banner around the letters instead of the letters themselves
+    #[arg(long, requires = "overlay", conflicts_with_all = ["high_contrast", "multiply_by_mask"])]
+    pub invert_text: bool,
+
+    /// Darken letters more over bright heatmap values and less over dark
+    /// ones, so the text fades into the dark parts of the banner
+    #[arg(long, requires = "overlay", conflicts_with_all = ["high_contrast", "multiply_by_mask"])]
+    pub text_opacity_by_value: bool,
+
+    /// Letter brightness factors over the brightest and darkest values with
+    /// --text-opacity-by-value, from 0 (black) to 1 (undarkened)
+    #[arg(long, num_args = 2, value_names = ["BRIGHT", "DARK"], default_values_t = [0.2, 1.0],
+          requires = "text_opacity_by_value", value_parser = parse_factor)]
+    pub text_darken_range: Vec<f64>,
+
+    /// Fade the letters' darkening out over this many cells around them,
+    /// instead of stopping hard at their edges
+    #[arg(long, value_name = "N", default_value_t = 0, requires = "overlay",
+          conflicts_with_all = ["high_contrast", "multiply_by_mask", "invert_text"])]
+    pub text_mask_feather: usize,
+
+    /// Font characters covering at most this much of a cell (0 to 1) count as
+    /// background, so shading like `.` and `:` can be left out of the letters
+    #[arg(long, value_name = "F", default_value_t = 0.0, value_parser = parse_threshold)]
+    pub glyph_threshold: f64,
+
+    /// Color theme for the heatmap
+    #[arg(long, default_value = DEFAULT_THEME)]
+    pub theme: String,
+
+    /// Map values straight to shades of gray instead of a theme
+    #[arg(long, conflicts_with_all = ["theme", "theme2", "theme_file"])]
+    pub grayscale: bool,
+
+    /// Second theme to blend the first with
+    #[arg(long, value_name = "THEME")]
+    pub theme2: Option<String>,
+
+    /// How far to blend from --theme (0) to --theme2 (1)
+    #[arg(long, value_name = "T", default_value_t = 0.0, requires = "theme2", value_parser = parse_blend)]
+    pub theme_blend: f64,
+
+    /// Build the theme from the dominant colors of an image, darkest first
+    #[arg(long, value_name = "PATH", conflicts_with_all = ["theme", "grayscale"])]
+    pub theme_from_image: Option<PathBuf>,
+
+    /// Load extra themes from a TOML or JSON file, overriding built-ins of the same name
+    #[arg(long, value_name = "PATH")]
+    pub theme_file: Option<PathBuf>,
+
+    /// Color encoding of the output
+    #[arg(long, value_enum, default_value_t = ColorMode::Truecolor)]
+    pub color_mode: ColorMode,
+
+    /// Limit the banner to N colors, picked by median cut from the colors it
+    /// uses, with the backgrounds dithered to hide the bands
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(u64).range(1..))]
+    pub max_colors: Option<u64>,
+
+    /// Always emit 24-bit color escapes, ignoring --color-mode and the
+    /// terminal, and without --random fade with a fixed seed, so the output
+    /// bytes only depend on the flags. Meant for golden-file tests and
+    /// scripts
+    #[arg(long)]
+    pub reproducible_colors: bool,
+
+    /// Print plain characters with no escape codes: the background as
+    /// density characters and the letters as drawn by the font
+    #[arg(long, conflicts_with_all = ["animate", "at_row", "at_col"])]
+    pub strip_ansi: bool,
+
+    /// Write each row as soon as it's computed instead of building the whole
+    /// banner first. Needs --min-value and --max-value, or
+    /// --normalize-per-row, since rows are normalized on their own
+    #[arg(long, conflicts_with_all = [
+        "animate", "png", "output", "sixel", "kitty", "at_row", "at_col", "stdin_lines",
+        "seed_grid", "tile_output", "compare_octaves", "compare_scale",
+        "glow", "histogram_equalize", "smooth_normalize", "noise_clamp", "noise_mask",
+        "palette_reverse_per_octave", "max_colors", "emit_json_cells", "text_seed",
+        "resample_filter", "bg_alpha",
+    ])]
+    pub stream: bool,
+
+    /// How to bring colors outside the displayable range back into it
+    #[arg(long, value_enum, default_value_t = Gamut::Clip)]
+    pub clamp_to_gamut: Gamut,
+
+    /// Show the colors as someone with this color vision deficiency sees them
+    #[arg(long, value_enum, value_name = "KIND")]
+    pub color_blind: Option<ColorBlindness>,
+
+    /// With --color-blind, adjust the colors to stay distinguishable for that
+    /// deficiency instead of simulating it
+    #[arg(long, requires = "color_blind")]
+    pub cvd_safe: bool,
+
+    /// Clamp raw values to this range before normalizing, either absolute
+    /// values or percentiles like 2% 98%
+    #[arg(long, num_args = 2, value_names = ["LO", "HI"], allow_negative_numbers = true, value_parser = parse_clamp_bound)]
+    pub noise_clamp: Option<Vec<ClampBound>>,
+
+    /// Value mapped to the bottom of the gradient [default: field minimum]
+    #[arg(long, allow_negative_numbers = true)]
+    pub min_value: Option<f64>,
+
+    /// Value mapped to the top of the gradient [default: field maximum]
+    #[arg(long, allow_negative_numbers = true)]
+    pub max_value: Option<f64>,
+
+    /// Stretch every row over the full gradient on its own
+    #[arg(long, conflicts_with_all = ["min_value", "max_value"])]
+    pub normalize_per_row: bool,
+
+    /// Spread values so every part of the gradient covers as many cells,
+    /// instead of stretching them linearly
+    #[arg(long, conflicts_with_all = ["min_value", "max_value", "normalize_per_row"])]
+    pub histogram_equalize: bool,
+
+    /// Normalize between the 1st and 99th percentiles of the field instead of
+    /// its extremes, so a few outlying cells can't flatten the contrast
+    #[arg(long, conflicts_with_all = ["min_value", "max_value", "noise_clamp", "histogram_equalize"])]
+    pub smooth_normalize: bool,
+
+    /// Reshape normalized values with an easing curve before coloring
+    #[arg(long, value_enum, default_value_t = ValueCurve::Linear)]
+    pub value_curve: ValueCurve,
+
+    /// Debugging aid: print each cell's normalized value as a digit from 0 to 9
+    #[arg(long, hide = true)]
+    pub cell_text: bool,
+
+    /// Draw the cells above --threshold like letters, so the noise's bright
+    /// blobs stand in for text
+    #[arg(long, conflicts_with_all = ["overlay", "smooth_text", "strip_ansi"])]
+    pub noise_threshold_text: bool,
+
+    /// Normalized value above which --noise-threshold-text fills a cell
+    #[arg(long, value_name = "V", default_value_t = 0.6, requires = "noise_threshold_text", value_parser = parse_threshold)]
+    pub threshold: f64,
+
+    /// Bloom the brightest values into cells up to this many cells away
+    #[arg(long, value_name = "RADIUS", value_parser = clap::value_parser!(u64).range(1..))]
+    pub glow: Option<u64>,
+
+    /// Snap normalized values to this many evenly spaced levels
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(u32).range(2..))]
+    pub quantize_value: Option<u32>,
+
+    /// Fill letters with density characters picked by the heatmap value
+    #[arg(long)]
+    pub glyph_charset: bool,
+
+    /// Read the --glyph-charset ramp from a file, one character per level
+    #[arg(long, value_name = "PATH", requires = "glyph_charset")]
+    pub charset_file: Option<PathBuf>,
+
+    /// Character to fill the background with, drawn in the text color
+    #[arg(long, value_name = "CHAR", value_parser = parse_grapheme)]
+    pub background_char: Option<String>,
+
+    /// Draw a drop shadow behind the letters
+    #[arg(long)]
+    pub text_shadow: bool,
+
+    /// Offset of the shadow from the letters, as columns,rows
+    #[arg(long, value_name = "X,Y", default_value = "1,1", value_parser = parse_offset)]
+    pub shadow_offset: (i64, i64),
+
+    /// Color of the shadow
+    #[arg(long, value_name = "HEX", default_value = "#111218", value_parser = parse_hex_color)]
+    pub shadow_color: (u8, u8, u8),
+
+    /// Extrude the letters DEPTH cells back, each step a darker copy
+    #[arg(long, value_name = "DEPTH", requires = "overlay", conflicts_with_all = ["multiply_by_mask", "invert_text"],
+          value_parser = clap::value_parser!(u64).range(1..))]
+    pub text_3d: Option<u64>,
+
+    /// Which way --text-3d extrudes the letters
+    #[arg(long, value_enum, default_value_t = Extrusion::DownRight, requires = "text_3d")]
+    pub text_3d_direction: Extrusion,
+
+    /// Only show the heatmap inside the letters
+    #[arg(long, requires = "overlay")]
+    pub multiply_by_mask: bool,
+
+    /// Fill the letters from a second noise field with this seed, so their
+    /// texture differs from the banner around them. Without a value the
+    /// seed is the next one after --random
+    #[arg(long, value_name = "SEED", num_args = 0..=1, requires = "overlay", conflicts_with = "from_grid")]
+    pub text_seed: Option<Option<u64>>,
+
+    /// Color outside the letters with --multiply-by-mask
+    #[arg(long, value_name = "HEX", default_value = "#000000", value_parser = parse_hex_color)]
+    pub mask_outside_color: (u8, u8, u8),
+
+    /// Draw black letters over a background lightened for strong contrast
+    #[arg(long)]
+    pub high_contrast: bool,
+
+    /// Order in which letters are laid out
+    #[arg(long, value_enum, default_value_t = Direction::Auto)]
+    pub direction: Direction,
+
+    /// Render a second banner with these octaves to the right, for comparison
+    #[arg(long, value_name = "N", conflicts_with_all = ["from_grid", "animate", "stdin_lines"])]
+    pub compare_octaves: Option<usize>,
+
+    /// Render a second banner with this scale to the right, for comparison
+    #[arg(long, value_name = "SCALE", conflicts_with_all = ["from_grid", "animate", "stdin_lines"])]
+    pub compare_scale: Option<f64>,
+
+    /// Tile the banner area with ROWS x COLS small banners, each labeled with
+    /// its seed, counting up from --random (or 0)
+    #[arg(long, num_args = 2, value_names = ["ROWS", "COLS"], value_parser = clap::value_parser!(u64).range(1..),
+          conflicts_with_all = ["from_grid", "animate", "stdin_lines", "compare_octaves", "compare_scale"])]
+    pub seed_grid: Option<Vec<u64>>,
+
+    /// Repeat the banner NX times across and NY times down, clipping tiles
+    /// that run past the terminal. --width sets the width of each tile,
+    /// otherwise the tiles split the available width
+    #[arg(long, num_args = 2, value_names = ["NX", "NY"], value_parser = clap::value_parser!(u64).range(1..),
+          conflicts_with_all = ["from_grid", "animate", "stdin_lines", "compare_octaves", "compare_scale", "seed_grid"])]
+    pub tile_output: Option<Vec<u64>>,
+
+    /// Advance the seed by this much from one --tile-output tile to the next,
+    /// so the pattern varies between them
+    #[arg(long, value_name = "N", default_value_t = 0, requires = "tile_output")]
+    pub tile_seed_offset: u64,
+
+    /// Draw the banner starting at this terminal row (1-based), leaving the
+    /// cursor where it was
+    #[arg(long, value_name = "ROW", value_parser = clap::value_parser!(u16).range(1..), conflicts_with_all = ["animate", "stdin_lines"])]
+    pub at_row: Option<u16>,
+
+    /// Draw the banner starting at this terminal column (1-based), leaving
+    /// the cursor where it was
+    #[arg(long, value_name = "COL", value_parser = clap::value_parser!(u16).range(1..), conflicts_with_all = ["animate", "stdin_lines"])]
+    pub at_col: Option<u16>,
+
+    /// Continuously morph the noise until interrupted
+    #[arg(long, conflicts_with = "from_grid")]
+    pub animate: bool,
+
+    /// Animation speed, in noise units per second
+    #[arg(long, default_value_t = 0.4, requires = "animate")]
+    pub speed: f64,
+
+    /// Noise axis the animation moves along: z morphs the pattern in place,
+    /// x and y pan across it
+    #[arg(long, value_enum, default_value_t = AnimateAxis::Z, requires = "animate")]
+    pub animate_axis: AnimateAxis,
+
+    /// Drop octaves, then sample more coarsely, whenever an animation frame
+    /// takes longer than MS milliseconds to draw. Quality is never raised
+    /// again, so it settles rather than flickering
+    #[arg(long, value_name = "MS", requires = "animate", value_parser = clap::value_parser!(u64).range(1..))]
+    pub render_budget: Option<u64>,
+
+    /// Upper limit on animation frames per second
+    #[arg(long, value_name = "FPS", default_value_t = 20.0, requires = "animate", value_parser = parse_fps)]
+    pub max_fps: f64,
+
+    /// Move through time in a circle, so the animation loops with no jump
+    /// back to the start
+    #[arg(long, requires = "animate", conflicts_with = "animate_axis")]
+    pub loop_seamless_time: bool,
+
+    /// Frames in one --loop-seamless-time loop
+    #[arg(long, value_name = "N", default_value_t = 120, value_parser = clap::value_parser!(u64).range(1..), requires = "loop_seamless_time")]
+    pub loop_frames: u64,
+
+    /// Draw the animation on the alternate screen, restoring the terminal on exit
+    #[arg(long, requires = "animate")]
+    pub alt_screen: bool,
+
+    /// Show the selected theme's gradient across the full width and exit
+    #[arg(long)]
+    pub palette_preview: bool,
+
+    /// List the available themes and exit
+    #[arg(long)]
+    pub list_themes: bool,
+
+    /// Start from the flags saved under this name in the profile file.
+    /// Flags given on the command line still win
+    #[arg(long, value_name = "NAME")]
+    pub profile: Option<String>,
+
+    /// Read --profile presets from this TOML file
+    #[arg(long, value_name = "PATH", default_value = DEFAULT_PROFILE_FILE)]
+    pub profile_file: PathBuf,
+
+    /// List the profiles in the profile file and exit
+    #[arg(long)]
+    pub list_profiles: bool,
+
+    /// Print every setting, defaults included and with the seed resolved, as
+    /// a profile under NAME and exit
+    #[arg(long, value_name = "NAME", num_args = 0..=1, default_missing_value = "exported")]
+    pub export_config: Option<String>,
+
+    /// Write the current banner width, detected from the terminal unless
+    /// --width is given, into --export-config. A --width given when the
+    /// profile is used still wins
+    #[arg(long, requires = "export_config")]
+    pub snapshot: bool,
+
+    /// Draw the banner saved by --export-config in this file again. Flags
+    /// given on the command line still win
+    #[arg(long, value_name = "PATH", conflicts_with_all = ["profile", "profile_file", "list_profiles"])]
+    pub reproduce: Option<PathBuf>,
+
+    /// Print the size of the drawn text as COLSxROWS and exit
+    #[arg(long, requires = "overlay")]
+    pub measure_text: bool,
+
+    /// Write the banner to a PNG file instead of the terminal
+    #[arg(long, value_name = "PATH", conflicts_with_all = ["animate", "stdin_lines"])]
+    pub png: Option<PathBuf>,
+
+    /// Write the banner to a file, in a format picked from its extension:
+    /// .png, .six or .sixel, .json for --emit-json-cells, or .txt or .ans for
+    /// escape-coded text. --sixel and --kitty override the extension
+    #[arg(long, value_name = "PATH", conflicts_with_all = ["animate", "stdin_lines", "png"])]
+    pub output: Option<PathBuf>,
+
+    /// Print every cell as JSON instead of drawing the banner: an array of
+    /// {"row", "col", "value", "bg": [r, g, b], "fg": [r, g, b], "glyph"}
+    /// objects in reading order, with the normalized value behind each cell
+    #[arg(long, conflicts_with_all = ["animate", "stdin_lines", "png", "sixel", "kitty"])]
+    pub emit_json_cells: bool,
+
+    /// Draw the banner as a Sixel image, for terminals that support it
+    #[arg(long, conflicts_with_all = ["animate", "stdin_lines", "png"])]
+    pub sixel: bool,
+
+    /// Draw the banner as an image with the Kitty graphics protocol (Kitty,
+    /// WezTerm, Konsole)
+    #[arg(long, conflicts_with_all = ["animate", "stdin_lines", "png", "sixel"])]
+    pub kitty: bool,
+
+    /// Width to height ratio of one cell in image exports
+    #[arg(long, value_name = "W:H", default_value = "1:2", value_parser = parse_aspect)]
+    pub pixel_aspect: (u32, u32),
+
+    /// Letterbox image exports to this resolution, padding with black
+    #[arg(long, value_name = "WxH", value_parser = parse_size)]
+    pub png_size: Option<(u32, u32)>,
+
+    /// Make the background of PNG and Kitty exports transparent: cells fade
+    /// out with their value, or with a threshold drop out below it, while
+    /// letters stay opaque. Sixel, JSON and terminal output have no alpha and
+    /// ignore it
+    #[arg(long, value_name = "THRESHOLD", num_args = 0..=1, value_parser = parse_threshold)]
+    pub bg_alpha: Option<Option<f64>>,
+
+    /// Report the color support of the terminal and exit
+    #[arg(long)]
+    pub probe: bool,
+
+    /// Print statistics of the raw field to stderr
+    #[arg(long)]
+    pub stats: bool,
+
+    /// Report details of the rendered output to stderr
+    #[arg(short, long)]
+    pub verbose: bool,
+
+    /// Don't echo the seed --seed-from-time picks to stderr. Warnings are
+    /// still shown, and --verbose echoes the seed anyway
+    #[arg(long)]
+    pub quiet_seed: bool,
+}
+
+/// Print direction of the overlay text
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum Direction {
+    /// Use the direction declared by the font
+    Auto,
+    /// Left to right
+    Ltr,
+    /// Right to left
+    Rtl,
+}
+
+/// Where the fade darkens the heatmap
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum FadeDirection {
+    Left,
+    Right,
+    Top,
+    Bottom,
+    /// Darkest in the middle
+    Center,
+    /// Darkest in the corners, like a vignette
+    Edges,
+}
+
+/// Filter used to scale a grid up
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum Resample {
+    /// Repeat each value, keeping the blocks
+    Nearest,
+    /// Blend linearly between neighbouring values
+    Bilinear,
+    /// Windowed sinc over three values each side, sharper than bilinear
+    Lanczos,
+}
+
+/// Direction letters are extruded in by --text-3d
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum Extrusion {
+    DownRight,
+    DownLeft,
+    UpRight,
+    UpLeft,
+}
+
+impl Extrusion {
+    /// One step of the extrusion, as columns and rows
+    pub fn step(self) -> (i64, i64) {
+        match self {
+            Extrusion::DownRight => (1, 1),
+            Extrusion::DownLeft => (-1, 1),
+            Extrusion::UpRight => (1, -1),
+            Extrusion::UpLeft => (-1, -1),
+        }
+    }
+}
+
+/// How text taller than the banner is fitted into it
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum Overflow {
+    /// Keep the bottom rows of the text
+    CropTop,
+    /// Keep the top rows of the text
+    CropBottom,
+    /// Keep the middle rows of the text
+    CropCenter,
+    /// Scale the letters down until they fit
+    Shrink,
+}
+
+/// Noise axis that advances with time when animating
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum AnimateAxis {
+    X,
+    Y,
+    Z,
+}
+
+/// Easing curve applied to normalized values
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum ValueCurve {
+    Linear,
+    /// Slow start, spending more of the field on low colors
+    EaseIn,
+    /// Slow finish, spending more of the field on high colors
+    EaseOut,
+    /// Slow at both ends
+    EaseInOut,
+    /// Like ease-in-out, but gentler
+    Smoothstep,
+}
+
+/// Gradient noise generator
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum NoiseKind {
+    /// Classic Perlin noise
+    Perlin,
+    /// OpenSimplex noise, with fewer axis-aligned artifacts
+    #[value(alias = "simplex")]
+    OpenSimplex,
+    /// Distance to the nearest Worley cell point, for cellular textures
+    Worley,
+}
+
+/// Function sampled for the heatmap
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum FieldKind {
+    /// Fractal noise from the --noise generator
+    Perlin,
+    /// A gradient rising from left to right
+    Linear,
+    /// Brightest in the middle, darkest in the corners
+    Radial,
+    /// Waves along both axes, drifting when animated
+    Sine,
+    /// Alternating squares
+    Checker,
+}
+
+/// Vertical position of the overlay text
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum VerticalAlign {
+    Top,
+    Middle,
+    Bottom,
+}
+
+/// One end of the --noise-clamp range
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ClampBound {
+    /// A raw noise value
+    Absolute(f64),
+    /// A percentile of the field's values, from 0 to 100
+    Percentile(f64),
+}
+
+/// Parse a raw value, or a percentile ending in `%`.
+fn parse_clamp_bound(s: &str) -> Result<ClampBound, String> {
+    match s.strip_suffix('%') {
+        Some(p) => match p.parse::<f64>() {
+            Ok(p) if (0.0..=100.0).contains(&p) => Ok(ClampBound::Percentile(p)),
+            Ok(_) => Err(format!("invalid percentile {:?}, expected 0% to 100%", s)),
+            Err(e) => Err(e.to_string()),
+        },
+        None => s
+            .parse::<f64>()
+            .map(ClampBound::Absolute)
+            .map_err(|e| e.to_string()),
+    }
+}
+
+/// Parse an `x,y` cell offset.
+fn parse_offset(s: &str) -> Result<(i64, i64), String> {
+    let parse = |v: &str| v.trim().parse::<i64>().map_err(|e| e.to_string());
+    match s.split_once(',') {
+        Some((x, y)) => Ok((parse(x)?, parse(y)?)),
+        None => Err(format!("invalid offset {:?}, expected X,Y", s)),
+    }
+}
+
+/// Parse a pair of positive integers separated by `sep`.
+fn parse_pair(s: &str, sep: char, expected: &str) -> Result<(u32, u32), String> {
+    let parse = |v: &str| match v.trim().parse::<u32>() {
+        Ok(0) => Err(format!(
+            "invalid value {:?}, expected {} above zero",
+            s, expected
+        )),
+        Ok(n) => Ok(n),
+        Err(e) => Err(e.to_string()),
+    };
+    match s.split_once(sep) {
+        Some((a, b)) => Ok((parse(a)?, parse(b)?)),
+        None => Err(format!("invalid value {:?}, expected {}", s, expected)),
+    }
+}
+
+/// Parse a `w:h` aspect ratio.
+fn parse_aspect(s: &str) -> Result<(u32, u32), String> {
+    parse_pair(s, ':', "W:H")
+}
+
+/// Parse a `WxH` resolution.
+fn parse_size(s: &str) -> Result<(u32, u32), String> {
+    parse_pair(s, 'x', "WxH")
+}
+
+/// Parse a positive frame rate.
+fn parse_fps(s: &str) -> Result<f64, String> {
+    match s.parse::<f64>() {
+        Ok(fps) if fps > 0.0 && fps.is_finite() => Ok(fps),
+        Ok(_) => Err(format!(
+            "invalid frame rate {:?}, expected a positive number",
+            s
+        )),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+/// Parse any finite number.
+fn parse_finite(s: &str) -> Result<f64, String> {
+    match s.parse::<f64>() {
+        Ok(x) if x.is_finite() => Ok(x),
+        Ok(_) => Err(format!("invalid number {:?}, expected a finite one", s)),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+/// Largest --offset-x or --offset-y, in cells. Far past this the noise
+/// coordinates lose their precision, and eventually the noise fails on them
+const MAX_OFFSET: f64 = 1e9;
+
+/// Parse an offset into the noise field, in cells.
+fn parse_noise_offset(s: &str) -> Result<f64, String> {
+    match s.parse::<f64>() {
+        Ok(offset) if offset.abs() <= MAX_OFFSET => Ok(offset),
+        Ok(_) => Err(format!(
+            "invalid offset {:?}, expected a number from -{:e} to {:e}",
+            s, MAX_OFFSET, MAX_OFFSET
+        )),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+/// Parse a blend factor between 0 and 1.
+fn parse_blend(s: &str) -> Result<f64, String> {
+    match s.parse::<f64>() {
+        Ok(t) if (0.0..=1.0).contains(&t) => Ok(t),
+        Ok(_) => Err(format!(
+            "invalid blend {:?}, expected a value from 0 to 1",
+            s
+        )),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+/// Parse a detail falloff between 0 and 1.
+fn parse_falloff(s: &str) -> Result<f64, String> {
+    match s.parse::<f64>() {
+        Ok(f) if (0.0..=1.0).contains(&f) => Ok(f),
+        Ok(_) => Err(format!(
+            "invalid falloff {:?}, expected a value from 0 to 1",
+            s
+        )),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+/// Parse a glyph coverage threshold between 0 and 1.
+fn parse_threshold(s: &str) -> Result<f64, String> {
+    match s.parse::<f64>() {
+        Ok(t) if (0.0..=1.0).contains(&t) => Ok(t),
+        Ok(_) => Err(format!(
+            "invalid threshold {:?}, expected a value from 0 to 1",
+            s
+        )),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+/// Parse a positive fade steepness.
+fn parse_steepness(s: &str) -> Result<f64, String> {
+    match s.parse::<f64>() {
+        Ok(v) if v > 0.0 && v.is_finite() => Ok(v),
+        Ok(_) => Err(format!(
+            "invalid steepness {:?}, expected a positive number",
+            s
+        )),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+/// Parse a brightness factor between 0 and 1.
+fn parse_factor(s: &str) -> Result<f64, String> {
+    match s.parse::<f64>() {
+        Ok(f) if (0.0..=1.0).contains(&f) => Ok(f),
+        Ok(_) => Err(format!(
+            "invalid factor {:?}, expected a value from 0 to 1",
+            s
+        )),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+/// Parse a single character, which may span several code points.
+fn parse_grapheme(s: &str) -> Result<String, String> {
+    if s.graphemes(true).count() == 1 {
+        Ok(s.to_string())
+    } else {
+        Err(format!("invalid character {:?}, expected exactly one", s))
+    }
+}
@@ -0,0 +1,53 @@
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::path::Path;
+
+/// Everything that can go wrong while building a banner
+#[derive(Debug)]
+pub enum BannerError {
+    /// The figlet font could not be parsed
+    FontLoad(String),
+    /// The text could not be drawn with the font
+    TextConvert(String),
+    /// Reading an input file or writing the banner failed
+    Io(io::Error),
+    /// A flag or input file holds an unusable value
+    InvalidConfig(String),
+}
+
+impl BannerError {
+    /// Wrap an error from reading `path`, keeping the path in the message.
+    pub fn read(path: &Path, err: io::Error) -> Self {
+        BannerError::Io(io::Error::new(
+            err.kind(),
+            format!("failed to read {}: {}", path.display(), err),
+        ))
+    }
+}
+
+impl fmt::Display for BannerError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            BannerError::FontLoad(msg) => write!(f, "failed to load font: {}", msg),
+            BannerError::TextConvert(msg) => write!(f, "failed to render text: {}", msg),
+            BannerError::Io(err) => write!(f, "{}", err),
+            BannerError::InvalidConfig(msg) => write!(f, "{}", msg),
+        }
+    }
+}
+
+impl Error for BannerError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            BannerError::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for BannerError {
+    fn from(err: io::Error) -> Self {
+        BannerError::Io(err)
+    }
+}
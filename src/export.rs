@@ -0,0 +1,316 @@
+use crate::color::{ansi256_rgb, Ansi256};
+use crate::config::Resample;
+use crate::error::BannerError;
+use crate::render::Cell;
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use image::imageops::{self, FilterType};
+use image::{Rgba, RgbaImage};
+use std::fmt::Write;
+
+/// Height in pixels of one cell in image exports, before letterboxing
+const CELL_HEIGHT: u32 = 16;
+
+/// Pixel size of one cell for a `w:h` cell aspect ratio.
+fn cell_size((w, h): (u32, u32)) -> (u32, u32) {
+    let width = (CELL_HEIGHT as f64 * w as f64 / h as f64).round() as u32;
+    (width.max(1), CELL_HEIGHT)
+}
+
+/// RGB pixels in rows from the top left
+struct Image {
+    width: u32,
+    height: u32,
+    pixels: Vec<(u8, u8, u8)>,
+    /// Opacity of each pixel
+    alpha: Vec<u8>,
+}
+
+/// Rasterize cells to RGB pixels, each cell a solid block of its background
+/// color.
+///
+/// Cells are drawn with the `aspect` width to height ratio of a terminal
+/// character. With a target `size` the image is scaled to fit inside it and
+/// centered, padding the rest with black. Glyph characters aren't drawn,
+/// letters show up through their darkened backgrounds. Any `filter` but
+/// nearest blends the blocks into each other.
+///
+/// With an `alpha` grid each cell takes its opacity from it and the padding
+/// is transparent, otherwise every pixel is opaque.
+fn rasterize(
+    cells: &[Vec<Cell>],
+    alpha: Option<&[Vec<u8>]>,
+    aspect: (u32, u32),
+    size: Option<(u32, u32)>,
+    filter: Resample,
+) -> Result<Image, BannerError> {
+    let rows = cells.len() as u32;
+    let cols = cells.first().map_or(0, |row| row.len()) as u32;
+    if rows == 0 || cols == 0 {
+        return Err(BannerError::InvalidConfig(
+            "cannot export an empty banner".to_string(),
+        ));
+    }
+
+    let (cell_w, cell_h) = cell_size(aspect);
+    let (banner_w, banner_h) = (cols * cell_w, rows * cell_h);
+    let (width, height) = size.unwrap_or((banner_w, banner_h));
+
+    // Fit the banner inside the target, then center it
+    let fit = (width as f64 / banner_w as f64).min(height as f64 / banner_h as f64);
+    let (fit_w, fit_h) = (
+        (banner_w as f64 * fit).round() as u32,
+        (banner_h as f64 * fit).round() as u32,
+    );
+    let (left, top) = ((width - fit_w) / 2, (height - fit_h) / 2);
+
+    let opacity = |i: usize, j: usize| {
+        alpha.map_or(u8::MAX, |alpha| {
+            alpha
+                .get(i)
+                .and_then(|row| row.get(j))
+                .copied()
+                .unwrap_or(0)
+        })
+    };
+    let mut pixels = vec![(0, 0, 0); (width * height) as usize];
+    let mut alphas = vec![if alpha.is_some() { 0 } else { u8::MAX }; (width * height) as usize];
+    let filter = match filter {
+        Resample::Nearest => None,
+        Resample::Bilinear => Some(FilterType::Triangle),
+        Resample::Lanczos => Some(FilterType::Lanczos3),
+    };
+    if let Some(filter) = filter {
+        let grid = RgbaImage::from_fn(cols, rows, |j, i| {
+            let (r, g, b) = cells[i as usize]
+                .get(j as usize)
+                .map_or((0, 0, 0), |cell| cell.bg);
+            Rgba([r, g, b, opacity(i as usize, j as usize)])
+        });
+        let scaled = imageops::resize(&grid, fit_w, fit_h, filter);
+        for (x, y, pixel) in scaled.enumerate_pixels() {
+            let [r, g, b, a] = pixel.0;
+            let k = ((y + top) * width + x + left) as usize;
+            pixels[k] = (r, g, b);
+            alphas[k] = a;
+        }
+        return Ok(Image {
+            width,
+            height,
+            pixels,
+            alpha: alphas,
+        });
+    }
+    for y in top..top + fit_h {
+        let i = (((y - top) as f64 / fit / cell_h as f64) as usize).min(rows as usize - 1);
+        for x in left..left + fit_w {
+            let j = (((x - left) as f64 / fit / cell_w as f64) as usize).min(cols as usize - 1);
+            let k = (y * width + x) as usize;
+            pixels[k] = cells[i][j].bg;
+            alphas[k] = opacity(i, j);
+        }
+    }
+    Ok(Image {
+        width,
+        height,
+        pixels,
+        alpha: alphas,
+    })
+}
+
+/// Encode cells as a PNG, laid out as described in [`rasterize`]. With an
+/// `alpha` grid the PNG is RGBA, otherwise RGB.
+pub(crate) fn png(
+    cells: &[Vec<Cell>],
+    alpha: Option<&[Vec<u8>]>,
+    aspect: (u32, u32),
+    size: Option<(u32, u32)>,
+    filter: Resample,
+) -> Result<Vec<u8>, BannerError> {
+    let image = rasterize(cells, alpha, aspect, size, filter)?;
+    let (data, color): (Vec<u8>, _) = if alpha.is_some() {
+        let data = image
+            .pixels
+            .iter()
+            .zip(&image.alpha)
+            .flat_map(|(&(r, g, b), &a)| [r, g, b, a])
+            .collect();
+        (data, png::ColorType::Rgba)
+    } else {
+        let data = image
+            .pixels
+            .iter()
+            .flat_map(|&(r, g, b)| [r, g, b])
+            .collect();
+        (data, png::ColorType::Rgb)
+    };
+
+    let mut out = Vec::new();
+    let mut encoder = png::Encoder::new(&mut out, image.width, image.height);
+    encoder.set_color(color);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder
+        .write_header()
+        .and_then(|mut writer| writer.write_image_data(&data))
+        .map_err(|e| BannerError::Io(std::io::Error::other(e)))?;
+    Ok(out)
+}
+
+/// Encode cells as a Sixel image, laid out as described in [`rasterize`].
+///
+/// Sixel images carry at most 256 color registers, so colors are mapped to
+/// the xterm 256-color palette and only the entries in use are defined.
+pub(crate) fn sixel(
+    cells: &[Vec<Cell>],
+    aspect: (u32, u32),
+    size: Option<(u32, u32)>,
+    filter: Resample,
+) -> Result<String, BannerError> {
+    let Image {
+        width,
+        height,
+        pixels,
+        ..
+    } = rasterize(cells, None, aspect, size, filter)?;
+    let (width, height) = (width as usize, height as usize);
+    let mut ansi256 = Ansi256::new();
+    let indices: Vec<u8> = pixels.iter().map(|&rgb| ansi256.index(rgb)).collect();
+
+    // Enter sixel mode with square pixels, then declare the image size
+    let mut out = format!("\x1bP0;1q\"1;1;{};{}", width, height);
+    let mut used = [false; 256];
+    for &index in &indices {
+        used[index as usize] = true;
+    }
+    for index in (0..=255u8).filter(|&index| used[index as usize]) {
+        let (r, g, b) = ansi256_rgb(index);
+        let percent = |c: u8| (c as u32 * 100 + 127) / 255;
+        write!(
+            out,
+            "#{};2;{};{};{}",
+            index,
+            percent(r),
+            percent(g),
+            percent(b)
+        )
+        .unwrap();
+    }
+
+    // Each band covers six pixel rows. Every color present in the band is
+    // drawn as its own pass over the band, returning to its start in between.
+    for band in (0..height).step_by(6) {
+        let band_rows = (height - band).min(6);
+        let mut colors: Vec<u8> = (0..band_rows)
+            .flat_map(|dy| &indices[(band + dy) * width..(band + dy + 1) * width])
+            .cloned()
+            .collect();
+        colors.sort_unstable();
+        colors.dedup();
+
+        for (n, &color) in colors.iter().enumerate() {
+            if n > 0 {
+                out.push('$');
+            }
+            write!(out, "#{}", color).unwrap();
+
+            let mut run: Option<(u8, usize)> = None;
+            for x in 0..width {
+                let bits = (0..band_rows)
+                    .filter(|&dy| indices[(band + dy) * width + x] == color)
+                    .fold(0u8, |bits, dy| bits | (1 << dy));
+                match &mut run {
+                    Some((b, count)) if *b == bits => *count += 1,
+                    _ => {
+                        if let Some((b, count)) = run {
+                            push_run(&mut out, b, count);
+                        }
+                        run = Some((bits, 1));
+                    }
+                }
+            }
+            if let Some((b, count)) = run {
+                push_run(&mut out, b, count);
+            }
+        }
+        out.push('-');
+    }
+    out.push_str("\x1b\\");
+    Ok(out)
+}
+
+/// Largest base64 payload allowed in one Kitty graphics escape
+const KITTY_CHUNK: usize = 4096;
+
+/// Wrap a PNG in Kitty graphics protocol escapes, displaying it at the cursor.
+///
+/// The protocol is understood by Kitty, WezTerm and Konsole, among others.
+/// Other terminals usually ignore the escapes and show nothing. The encoded
+/// image is split over several escapes, each flagged with whether more
+/// follow.
+pub(crate) fn kitty(png: &[u8]) -> String {
+    let payload = STANDARD.encode(png);
+    let chunks: Vec<&[u8]> = payload.as_bytes().chunks(KITTY_CHUNK).collect();
+    let mut out = String::new();
+    for (n, chunk) in chunks.iter().enumerate() {
+        // The first escape carries the format (PNG) and action (transmit and display)
+        let control = if n == 0 { "f=100,a=T," } else { "" };
+        let more = (n + 1 < chunks.len()) as u8;
+        write!(
+            out,
+            "\x1b_G{}m={};{}\x1b\\",
+            control,
+            more,
+            std::str::from_utf8(chunk).unwrap()
+        )
+        .unwrap();
+    }
+    out.push('\n');
+    out
+}
+
+/// Append `count` repeats of the sixel holding `bits`, run-length encoded.
+fn push_run(out: &mut String, bits: u8, count: usize) {
+    let ch = (0x3f + bits) as char;
+    if count > 3 {
+        write!(out, "!{}{}", count, ch).unwrap();
+    } else {
+        out.extend(std::iter::repeat_n(ch, count));
+    }
+}
+
+/// Describe every cell as JSON, for renderers that draw the banner
+/// themselves.
+///
+/// The output is an array of objects in reading order, one per cell:
+///
+/// ```json
+/// [{"row": 0, "col": 0, "value": 0.42, "bg": [40, 42, 54], "fg": [255, 255, 255], "glyph": " "}]
+/// ```
+///
+/// `value` is the normalized heatmap value behind the cell, before any fade,
+/// and `bg` and `fg` are its final background and text colors.
+pub(crate) fn json_cells(cells: &[Vec<Cell>], values: &[Vec<f64>]) -> String {
+    let cells: Vec<serde_json::Value> = cells
+        .iter()
+        .zip(values)
+        .enumerate()
+        .flat_map(|(i, (row, values))| {
+            row.iter()
+                .zip(values)
+                .enumerate()
+                .map(move |(j, (cell, &value))| {
+                    serde_json::json!({
+                        "row": i,
+                        "col": j,
+                        "value": value,
+                        "bg": [cell.bg.0, cell.bg.1, cell.bg.2],
+                        "fg": [cell.fg.0, cell.fg.1, cell.fg.2],
+                        "glyph": cell.ch,
+                    })
+                })
+        })
+        .collect();
+    let mut out = serde_json::Value::Array(cells).to_string();
+    out.push('\n');
+    out
+}
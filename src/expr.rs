@@ -0,0 +1,351 @@
+//! The `--expr` language for combining noise sources.
+//!
+//! An expression is arithmetic over numbers and noise terms:
+//!
+//! ```text
+//! 0.6 * perlin(s=100) + 0.4 * worley(s=40)
+//! ```
+//!
+//! Supported operators are `+`, `-`, `*`, `/`, unary minus and parentheses,
+//! with the usual precedence. Noise terms take optional `name=value`
+//! parameters, falling back to the matching flags when left out:
+//!
+//! - `perlin(s, o, p, l, f, seed)`: fractal Perlin noise with scale `s`, `o`
+//!   octaves, persistence `p`, lacunarity `l` and detail falloff `f`
+//! - `worley(s, seed)`: distance to the nearest Worley cell point, at scale `s`
+//!
+//! Numbers may have an exponent, like `1e3`. The scale must be positive,
+//! octaves and seeds whole numbers.
+//!
+//! The result is normalized like any other field, so terms don't need to be
+//! weighted to sum to one.
+
+use crate::config::HeatmapConfig;
+use crate::error::BannerError;
+use crate::field::{
+    check_sampling, loop_radius, noise_seed, time_coordinates, Fractal, TimeCoordinates,
+};
+use noise::core::worley::ReturnType;
+use noise::{NoiseFn, Perlin, Worley};
+use std::{fmt, slice};
+
+/// Value of an expression at a noise-space `y`, `x` and optional time.
+pub(crate) type Sampler = Box<dyn Fn(f64, f64, Option<f64>) -> f64>;
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Number(f64),
+    Ident(String),
+    Symbol(char),
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Token::Number(value) => write!(f, "{}", value),
+            Token::Ident(name) => write!(f, "{}", name),
+            Token::Symbol(c) => write!(f, "{}", c),
+        }
+    }
+}
+
+fn tokenize(src: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut chars = src.chars().peekable();
+    while let Some(&c) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+        } else if c.is_ascii_digit() || c == '.' {
+            let mut number = String::new();
+            while let Some(&c) = chars.peek().filter(|c| c.is_ascii_digit() || **c == '.') {
+                number.push(c);
+                chars.next();
+            }
+            // An exponent, only taken when digits follow so `2e` stays an error
+            let mut ahead = chars.clone();
+            if let Some(e) = ahead.next().filter(|c| *c == 'e' || *c == 'E') {
+                let sign = ahead.next_if(|c| *c == '+' || *c == '-');
+                if ahead.peek().is_some_and(|c| c.is_ascii_digit()) {
+                    number.push(e);
+                    number.extend(sign);
+                    while let Some(c) = ahead.next_if(|c| c.is_ascii_digit()) {
+                        number.push(c);
+                    }
+                    chars = ahead;
+                }
+            }
+            let value = number
+                .parse()
+                .map_err(|_| format!("invalid number {:?}", number))?;
+            tokens.push(Token::Number(value));
+        } else if c.is_ascii_alphabetic() || c == '_' {
+            let mut ident = String::new();
+            while let Some(&c) = chars
+                .peek()
+                .filter(|c| c.is_ascii_alphanumeric() || **c == '_')
+            {
+                ident.push(c);
+                chars.next();
+            }
+            tokens.push(Token::Ident(ident));
+        } else if "+-*/(),=".contains(c) {
+            tokens.push(Token::Symbol(c));
+            chars.next();
+        } else {
+            return Err(format!("unexpected character {:?}", c));
+        }
+    }
+    Ok(tokens)
+}
+
+/// Recursive-descent parser building a sampler closure as it goes
+struct Parser<'a> {
+    tokens: Vec<Token>,
+    pos: usize,
+    config: &'a HeatmapConfig,
+}
+
+impl Parser<'_> {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    fn eat(&mut self, symbol: char) -> bool {
+        if self.peek() == Some(&Token::Symbol(symbol)) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, symbol: char) -> Result<(), String> {
+        if self.eat(symbol) {
+            Ok(())
+        } else {
+            Err(format!("expected {:?}", symbol))
+        }
+    }
+
+    /// expr := term (('+' | '-') term)*
+    fn expr(&mut self) -> Result<Sampler, String> {
+        let mut lhs = self.term()?;
+        loop {
+            if self.eat('+') {
+                let rhs = self.term()?;
+                lhs = Box::new(move |y, x, t| lhs(y, x, t) + rhs(y, x, t));
+            } else if self.eat('-') {
+                let rhs = self.term()?;
+                lhs = Box::new(move |y, x, t| lhs(y, x, t) - rhs(y, x, t));
+            } else {
+                return Ok(lhs);
+            }
+        }
+    }
+
+    /// term := factor (('*' | '/') factor)*
+    fn term(&mut self) -> Result<Sampler, String> {
+        let mut lhs = self.factor()?;
+        loop {
+            if self.eat('*') {
+                let rhs = self.factor()?;
+                lhs = Box::new(move |y, x, t| lhs(y, x, t) * rhs(y, x, t));
+            } else if self.eat('/') {
+                let rhs = self.factor()?;
+                lhs = Box::new(move |y, x, t| lhs(y, x, t) / rhs(y, x, t));
+            } else {
+                return Ok(lhs);
+            }
+        }
+    }
+
+    /// factor := number | '-' factor | '(' expr ')' | ident '(' params ')'
+    fn factor(&mut self) -> Result<Sampler, String> {
+        match self.next() {
+            Some(Token::Number(value)) => Ok(Box::new(move |_, _, _| value)),
+            Some(Token::Symbol('-')) => {
+                let inner = self.factor()?;
+                Ok(Box::new(move |y, x, t| -inner(y, x, t)))
+            }
+            Some(Token::Symbol('(')) => {
+                let inner = self.expr()?;
+                self.expect(')')?;
+                Ok(inner)
+            }
+            Some(Token::Ident(name)) => {
+                let params = self.params()?;
+                self.noise(&name, params)
+            }
+            Some(Token::Symbol(c)) => Err(format!("unexpected {:?}", c)),
+            None => Err("unexpected end of expression".to_string()),
+        }
+    }
+
+    /// params := '(' (ident '=' number (',' ident '=' number)*)? ')'
+    fn params(&mut self) -> Result<Vec<(String, f64)>, String> {
+        self.expect('(')?;
+        let mut params = Vec::new();
+        if self.eat(')') {
+            return Ok(params);
+        }
+        loop {
+            let Some(Token::Ident(name)) = self.next() else {
+                return Err("expected a parameter name".to_string());
+            };
+            self.expect('=')?;
+            let value = match self.next() {
+                Some(Token::Number(value)) => value,
+                Some(Token::Symbol('-')) => match self.next() {
+                    Some(Token::Number(value)) => -value,
+                    _ => return Err(format!("expected a number for {:?}", name)),
+                },
+                _ => return Err(format!("expected a number for {:?}", name)),
+            };
+            params.push((name, value));
+            if self.eat(')') {
+                return Ok(params);
+            }
+            self.expect(',')?;
+        }
+    }
+
+    fn noise(&self, name: &str, params: Vec<(String, f64)>) -> Result<Sampler, String> {
+        let config = self.config;
+        let allowed: &[&str] = match name {
+            "perlin" => &["s", "o", "p", "l", "f", "seed"],
+            "worley" => &["s", "seed"],
+            _ => {
+                return Err(format!(
+                    "unknown noise {:?}, expected perlin or worley",
+                    name
+                ))
+            }
+        };
+        if let Some((key, _)) = params.iter().find(|(key, _)| !allowed.contains(&&key[..])) {
+            return Err(format!("{} has no parameter {:?}", name, key));
+        }
+        let param = |key: &str| params.iter().find(|(k, _)| k == key).map(|&(_, v)| v);
+        let bad = |key: &str, expected: &str| {
+            Err(format!(
+                "{} parameter {:?} must be {}, got {}",
+                name,
+                key,
+                expected,
+                param(key).unwrap_or_default()
+            ))
+        };
+        let whole = |v: f64| v.fract() == 0.0;
+        if param("s").is_some_and(|s| !(s.is_finite() && s > 0.0)) {
+            return bad("s", "a positive number");
+        }
+        if param("o").is_some_and(|o| !(whole(o) && o >= 1.0)) {
+            return bad("o", "a whole number from 1 up");
+        }
+        if param("seed")
+            .is_some_and(|seed| !(whole(seed) && (0.0..=u64::MAX as f64).contains(&seed)))
+        {
+            return bad("seed", "a whole number from 0 up");
+        }
+        for key in ["p", "l", "f"] {
+            if param(key).is_some_and(|v| !v.is_finite()) {
+                return bad(key, "a finite number");
+            }
+        }
+
+        let scale = param("s").unwrap_or(config.scale);
+        // The whole-field checks, rerun with this term's own parameters
+        let mut term = config.clone();
+        term.scale = scale;
+        if name == "perlin" {
+            term.octaves = param("o").map_or(config.octaves, |o| o as usize);
+            term.persistence = param("p").unwrap_or(config.persistence);
+            term.lacunarity = param("l").unwrap_or(config.lacunarity);
+        } else {
+            term.octaves = 1;
+        }
+        check_sampling(&term).map_err(|e| format!("{}: {}", name, e))?;
+        let seed = param("seed")
+            .map(|seed| noise_seed(seed as u64))
+            .or(config.random.map(noise_seed));
+        if name == "perlin" {
+            let perlin = seed.map_or_else(Perlin::default, Perlin::new);
+            let fractal = Fractal {
+                octaves: term.octaves,
+                persistence: term.persistence,
+                lacunarity: term.lacunarity,
+                detail_falloff: param("f").unwrap_or(config.detail_falloff),
+                octave_offset: config.octave_offset,
+                loop_radius: loop_radius(config),
+            };
+            Ok(Box::new(move |y, x, t| {
+                fractal.sample(slice::from_ref(&perlin), y / scale, x / scale, t)
+            }))
+        } else {
+            let worley = Worley::new(seed.unwrap_or(Worley::DEFAULT_SEED))
+                .set_return_type(ReturnType::Distance);
+            let radius = loop_radius(config);
+            Ok(Box::new(move |y, x, t| {
+                match t.map(|t| time_coordinates(t, radius)) {
+                    Some(TimeCoordinates::Linear(t)) => worley.get([y / scale, x / scale, t]),
+                    Some(TimeCoordinates::Loop(u, w)) => worley.get([y / scale, x / scale, u, w]),
+                    None => worley.get([y / scale, x / scale]),
+                }
+            }))
+        }
+    }
+}
+
+/// Parse `src` into a sampler, with noise parameters defaulting to `config`.
+pub(crate) fn parse(src: &str, config: &HeatmapConfig) -> Result<Sampler, BannerError> {
+    let invalid = |e: String| BannerError::InvalidConfig(format!("invalid --expr: {}", e));
+    let mut parser = Parser {
+        tokens: tokenize(src).map_err(invalid)?,
+        pos: 0,
+        config,
+    };
+    let sampler = parser.expr().map_err(invalid)?;
+    if let Some(token) = parser.peek() {
+        return Err(invalid(format!(
+            "unexpected \"{}\" after expression",
+            token
+        )));
+    }
+    Ok(sampler)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::Parser;
+
+    fn check(src: &str) -> Result<(), String> {
+        let config = HeatmapConfig::parse_from(["banner", "4", "-w", "20"]);
+        parse(src, &config).map(drop).map_err(|e| e.to_string())
+    }
+
+    #[test]
+    fn numbers_take_exponents() {
+        assert!(check("perlin(l=1e1, s=2.5E-1) * 1e-2").is_ok());
+        assert!(check("2e").is_err());
+    }
+
+    #[test]
+    fn noise_parameters_are_checked() {
+        for (src, key) in [
+            ("perlin(s=0)", "\"s\""),
+            ("worley(s=-1)", "\"s\""),
+            ("perlin(o=0)", "\"o\""),
+            ("perlin(o=2.5)", "\"o\""),
+            ("perlin(seed=-1)", "\"seed\""),
+        ] {
+            let err = check(src).unwrap_err();
+            assert!(err.contains(key), "{}: {}", src, err);
+        }
+    }
+}
@@ -0,0 +1,876 @@
+use crate::config::{
+    AnimateAxis, ClampBound, FieldKind, HeatmapConfig, NoiseKind, Resample, ValueCurve,
+};
+use crate::error::BannerError;
+use crate::expr::{self, Sampler};
+use crate::picture;
+use image::imageops::{self, FilterType};
+use image::GrayImage;
+use noise::core::worley::ReturnType;
+use noise::{NoiseFn, OpenSimplex, Perlin, Worley};
+use std::f64::consts::{PI, SQRT_2, TAU};
+use std::path::Path;
+use std::{env, fs};
+use termion::terminal_size;
+
+/// Resolve the banner width.
+///
+/// In order of precedence: the `--width` flag, the size of the terminal on
+/// stdout, the `COLUMNS` environment variable (which many shells export, and
+/// which survives piping), and finally 80 columns.
+pub fn output_width(width: Option<usize>) -> usize {
+    width
+        .or_else(|| terminal_size().ok().map(|(cols, _)| cols as usize))
+        .or_else(|| env::var("COLUMNS").ok()?.trim().parse().ok())
+        .unwrap_or(80)
+}
+
+/// Most octaves --auto-octaves will pick
+const MAX_AUTO_OCTAVES: usize = 32;
+
+/// The number of octaves that stay above the grid's Nyquist limit.
+///
+/// Neighbouring cells are `frequency / scale` noise units apart, and Perlin
+/// noise varies over about one unit, so octaves past `frequency = scale / 2`
+/// are sampled less than twice per feature and only add aliasing. This
+/// depends on the spacing of the cells, not how many there are.
+pub(crate) fn auto_octaves(scale: f64, lacunarity: f64) -> usize {
+    if lacunarity <= 1.0 {
+        // Frequencies never grow, so no octave ever crosses the limit
+        return MAX_AUTO_OCTAVES;
+    }
+    let limit = scale / 2.0;
+    let mut octaves = 1;
+    let mut frequency = lacunarity;
+    while frequency <= limit && octaves < MAX_AUTO_OCTAVES {
+        octaves += 1;
+        frequency *= lacunarity;
+    }
+    octaves
+}
+
+/// A noise function that can be sampled in 2D, 3D and 4D
+pub(crate) trait Source: NoiseFn<f64, 2> + NoiseFn<f64, 3> + NoiseFn<f64, 4> {}
+
+impl<N> Source for N where N: NoiseFn<f64, 2> + NoiseFn<f64, 3> + NoiseFn<f64, 4> {}
+
+/// Direction each octave's origin moves in under --octave-offset, chosen
+/// irrational so the shifts never land back on the lattice
+const OCTAVE_SHIFT: (f64, f64) = (SQRT_2, 1.618_033_988_749_895);
+
+/// How the layers of fractal noise are stacked
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct Fractal {
+    pub(crate) octaves: usize,
+    pub(crate) persistence: f64,
+    pub(crate) lacunarity: f64,
+    pub(crate) detail_falloff: f64,
+    /// Step between the origins of successive octaves, for --octave-offset
+    pub(crate) octave_offset: f64,
+    /// Radius of the time circle under --loop-seamless-time
+    pub(crate) loop_radius: Option<f64>,
+}
+
+/// Radius of the circle --loop-seamless-time takes through time.
+///
+/// The circle is as long as the line plain animation would cover in the
+/// same number of frames at the frame rate limit, so the pattern morphs at
+/// the same pace.
+pub(crate) fn loop_radius(config: &HeatmapConfig) -> Option<f64> {
+    config.loop_seamless_time.then(|| {
+        let length = config.speed * config.loop_frames as f64 / config.max_fps;
+        length / TAU
+    })
+}
+
+/// Noise coordinates at a time `t`: a point on the time axis, or under
+/// --loop-seamless-time (`radius` set, `t` a loop phase from 0 to 1) a point
+/// on a circle through two extra dimensions, returning to where it started
+/// after a full loop.
+pub(crate) fn time_coordinates(t: f64, radius: Option<f64>) -> TimeCoordinates {
+    match radius {
+        Some(r) => {
+            let (sin, cos) = (t * TAU).sin_cos();
+            TimeCoordinates::Loop(r * cos, r * sin)
+        }
+        None => TimeCoordinates::Linear(t),
+    }
+}
+
+/// Where in time noise is sampled, see [`time_coordinates`]
+#[derive(Clone, Copy, Debug)]
+pub(crate) enum TimeCoordinates {
+    Linear(f64),
+    Loop(f64, f64),
+}
+
+impl Fractal {
+    pub(crate) fn from_config(config: &HeatmapConfig) -> Self {
+        Self {
+            octaves: config.octaves,
+            persistence: config.persistence,
+            lacunarity: config.lacunarity,
+            detail_falloff: config.detail_falloff,
+            octave_offset: config.octave_offset,
+            loop_radius: loop_radius(config),
+        }
+    }
+
+    /// Sum `octaves` layers of `source` at `y`, `x`, each at a higher
+    /// frequency and lower amplitude than the last, normalized to the range of
+    /// one layer.
+    ///
+    /// On top of persistence, the step down to octave `k + 1` is scaled by
+    /// `(1 - detail_falloff)^k`, so the finest octaves fade out much faster
+    /// than the coarse ones.
+    ///
+    /// With a time `t` the noise is sampled in 3D, so successive times morph
+    /// smoothly into each other, or in 4D when looping.
+    ///
+    /// Octave `k` is drawn from `sources[k % sources.len()]`.
+    pub(crate) fn sample<N: Source>(&self, sources: &[N], y: f64, x: f64, t: Option<f64>) -> f64 {
+        let mut val = 0.0;
+        let mut max_value = 0.0;
+        self.layers(sources, y, x, t, |_, layer, amplitude| {
+            val += layer;
+            max_value += amplitude;
+        });
+        val / max_value
+    }
+
+    /// The octave contributing the most, up or down, to [`Fractal::sample`]
+    /// at `y`, `x`.
+    pub(crate) fn dominant_octave<N: Source>(
+        &self,
+        sources: &[N],
+        y: f64,
+        x: f64,
+        t: Option<f64>,
+    ) -> usize {
+        let mut strongest = (0, 0.0);
+        self.layers(sources, y, x, t, |k, layer, _| {
+            if layer.abs() > strongest.1 {
+                strongest = (k, layer.abs());
+            }
+        });
+        strongest.0
+    }
+
+    /// Pass each octave of `sources` at `y`, `x` to `f` with its index, its
+    /// weighted value and its amplitude.
+    fn layers<N, F>(&self, sources: &[N], y: f64, x: f64, t: Option<f64>, mut f: F)
+    where
+        N: Source,
+        F: FnMut(usize, f64, f64),
+    {
+        let time = t.map(|t| time_coordinates(t, self.loop_radius));
+        let mut frequency = 1.0;
+        let mut amplitude = 1.0;
+        let mut falloff = 1.0;
+        for k in 0..self.octaves {
+            let source = &sources[k % sources.len()];
+            // Different steps along each axis keep the shifted origins off
+            // the diagonal
+            let shift = k as f64 * self.octave_offset;
+            let (y, x) = (
+                y * frequency + shift * OCTAVE_SHIFT.0,
+                x * frequency + shift * OCTAVE_SHIFT.1,
+            );
+            let layer = match time {
+                Some(TimeCoordinates::Linear(t)) => source.get([y, x, t * frequency]),
+                Some(TimeCoordinates::Loop(u, w)) => {
+                    source.get([y, x, u * frequency, w * frequency])
+                }
+                None => source.get([y, x]),
+            };
+            f(k, layer * amplitude, amplitude);
+            amplitude *= self.persistence * falloff;
+            frequency *= self.lacunarity;
+            falloff *= 1.0 - self.detail_falloff;
+        }
+    }
+}
+
+/// The noise behind the banner: `--expr` if given, a --field pattern over a
+/// `rows` x `cols` grid, otherwise fractal noise configured by the flags.
+fn sampler(config: &HeatmapConfig, rows: usize, cols: usize) -> Result<Sampler, BannerError> {
+    if let Some(src) = &config.expr {
+        return expr::parse(src, config);
+    }
+    if config.field != FieldKind::Perlin {
+        return Ok(pattern(config.field, config.scale, rows, cols));
+    }
+
+    Ok(fractal_sampler(config, false))
+}
+
+/// Furthest from the origin noise is sampled. Well before the noise can no
+/// longer find the lattice cell of a point, nearby cells stop being told
+/// apart
+const MAX_COORDINATE: f64 = 1e15;
+
+/// Check that the flags shaping the noise coordinates give numbers the
+/// noise can sample, before any are computed.
+pub(crate) fn check_sampling(config: &HeatmapConfig) -> Result<(), BannerError> {
+    let invalid = |message: String| Err(BannerError::InvalidConfig(message));
+    if !(config.scale.is_finite() && config.scale > 0.0) {
+        return invalid(format!(
+            "--scale must be a positive number, got {}",
+            config.scale
+        ));
+    }
+    if !(config.lacunarity.is_finite() && config.lacunarity > 0.0) {
+        return invalid(format!(
+            "--lacunarity must be a positive number, got {}",
+            config.lacunarity
+        ));
+    }
+    if !(config.persistence.is_finite() && config.persistence >= 0.0) {
+        return invalid(format!(
+            "--persistence must be a number from 0 up, got {}",
+            config.persistence
+        ));
+    }
+    if config.octaves == 0 {
+        return invalid("--octaves must be at least 1".to_string());
+    }
+    for (flag, value) in [
+        ("--offset-x", config.offset_x),
+        ("--offset-y", config.offset_y),
+        ("--rotate", config.rotate),
+        ("--octave-offset", config.octave_offset),
+    ] {
+        if !value.is_finite() {
+            return invalid(format!("{} must be a finite number, got {}", flag, value));
+        }
+    }
+
+    // The finest octave reaches furthest, from the far corner of the grid
+    // turned to a diagonal by --rotate and moved by the offsets
+    let finest = (config.octaves - 1) as i32;
+    let rows = config.rows.unwrap_or_default() as f64;
+    let cols = output_width(config.width) as f64;
+    let corner = rows.hypot(cols) + config.offset_x.abs() + config.offset_y.abs();
+    let reach = corner / config.scale * config.lacunarity.max(1.0).powi(finest)
+        + config.octave_offset.abs() * OCTAVE_SHIFT.0.max(OCTAVE_SHIFT.1) * finest as f64;
+    if !reach.is_finite() || reach > MAX_COORDINATE {
+        return invalid(format!(
+            "the noise would be sampled too far out, {:e} units from the origin; \
+             raise --scale or lower --octaves, --lacunarity or the offsets",
+            reach
+        ));
+    }
+    if !config.persistence.powi(finest).is_finite() {
+        return invalid(format!(
+            "--persistence is too large for {} octaves",
+            config.octaves
+        ));
+    }
+    Ok(())
+}
+
+/// Seed used without --random
+pub const DEFAULT_SEED: u64 = Perlin::DEFAULT_SEED as u64;
+
+/// Fold a --random seed into the 32 bits the noise generators take, so
+/// seeds differing only in their high bits still give different noise.
+pub(crate) fn noise_seed(seed: u64) -> u32 {
+    (seed ^ (seed >> 32)) as u32
+}
+
+/// The generator for `kind`, seeded with `seed`
+fn source(kind: NoiseKind, seed: u32) -> Box<dyn Source> {
+    match kind {
+        NoiseKind::Perlin => Box::new(Perlin::new(seed)),
+        NoiseKind::OpenSimplex => Box::new(OpenSimplex::new(seed)),
+        NoiseKind::Worley => Box::new(Worley::new(seed).set_return_type(ReturnType::Distance)),
+    }
+}
+
+/// Sample the --noise generator, or the --octave-noise ones, as fractal
+/// noise, or with `octaves` for the index of each cell's
+/// [`Fractal::dominant_octave`].
+fn fractal_sampler(config: &HeatmapConfig, octaves: bool) -> Sampler {
+    // All generators take the seed the same way, falling back to the same
+    // fixed default
+    let seed = config.random.map_or(Perlin::DEFAULT_SEED, noise_seed);
+    let fractal = Fractal::from_config(config);
+    let scale = config.scale;
+    if let Some(kinds) = &config.octave_noise {
+        let sources: Vec<_> = kinds.iter().map(|&kind| source(kind, seed)).collect();
+        return fractal_fn(fractal, sources, scale, octaves);
+    }
+    // A single generator is sampled without boxing, for speed
+    match config.noise {
+        NoiseKind::Perlin => fractal_fn(fractal, vec![Perlin::new(seed)], scale, octaves),
+        NoiseKind::OpenSimplex => fractal_fn(fractal, vec![OpenSimplex::new(seed)], scale, octaves),
+        NoiseKind::Worley => {
+            let worley = Worley::new(seed).set_return_type(ReturnType::Distance);
+            fractal_fn(fractal, vec![worley], scale, octaves)
+        }
+    }
+}
+
+fn fractal_fn<N>(fractal: Fractal, sources: Vec<N>, scale: f64, octaves: bool) -> Sampler
+where
+    N: Source + 'static,
+{
+    if octaves {
+        Box::new(move |y, x, t| fractal.dominant_octave(&sources, y / scale, x / scale, t) as f64)
+    } else {
+        Box::new(move |y, x, t| fractal.sample(&sources, y / scale, x / scale, t))
+    }
+}
+
+/// Which cells of the banner have an odd octave as their strongest, for
+/// --palette-reverse-per-octave.
+///
+/// The octaves are sampled on the same grid as [`load_field`], so the bands
+/// line up with the field cell for cell.
+pub(crate) fn octave_bands(config: &HeatmapConfig, t: Option<f64>) -> Vec<Vec<bool>> {
+    let rows = config.rows.unwrap_or_default();
+    let cols = output_width(config.width);
+    let sample = fractal_sampler(config, true);
+    generate_field(config, &sample, rows, cols, t)
+        .into_iter()
+        .map(|row| row.into_iter().map(|k| k as usize % 2 == 1).collect())
+        .collect()
+}
+
+/// A geometric --field over a `rows` x `cols` grid, in the same -1 to 1
+/// range as noise.
+///
+/// Sine waves drift one wavelength per unit of `t`, so a --loop-seamless-time
+/// loop lines up.
+fn pattern(kind: FieldKind, scale: f64, rows: usize, cols: usize) -> Sampler {
+    let (rows, cols) = (rows.max(1) as f64, cols.max(1) as f64);
+    match kind {
+        FieldKind::Perlin => unreachable!("noise fields are sampled by fractal"),
+        FieldKind::Linear => Box::new(move |_, x, _| 2.0 * x / cols - 1.0),
+        FieldKind::Radial => Box::new(move |y, x, _| {
+            // Distance from the center, reaching 1 in the corners
+            let (y, x) = (y / rows - 0.5, x / cols - 0.5);
+            1.0 - 2.0 * (x * x + y * y).sqrt() / 0.5f64.sqrt()
+        }),
+        FieldKind::Sine => Box::new(move |y, x, t| {
+            let phase = t.unwrap_or_default();
+            (TAU * (x / scale - phase)).sin() * (TAU * y / scale).cos()
+        }),
+        FieldKind::Checker => Box::new(move |y, x, _| {
+            let square = (y / scale).floor() + (x / scale).floor();
+            if square.rem_euclid(2.0) == 0.0 {
+                1.0
+            } else {
+                -1.0
+            }
+        }),
+    }
+}
+
+/// Sample the noise for every cell of a `rows` x `cols` grid.
+///
+/// `t` selects a slice along the time axis of the noise when animating, or
+/// with `--animate-axis x` or `y` an offset along that axis, in noise units.
+/// With `--transpose` cell `i`, `j` reads the noise at `j`, `i` instead.
+/// With `--downsample N` only every Nth cell in each direction is sampled,
+/// and its value fills the N x N block below and right of it.
+fn generate_field(
+    config: &HeatmapConfig,
+    sample: &Sampler,
+    rows: usize,
+    cols: usize,
+    t: Option<f64>,
+) -> Vec<Vec<f64>> {
+    let step = config.downsample as usize;
+    let coarse: Vec<Vec<f64>> = (0..rows.div_ceil(step))
+        .map(|i| coarse_row(config, sample, i, rows, cols, t))
+        .collect();
+    if step == 1 {
+        return coarse;
+    }
+    if config.resample_filter != Resample::Nearest {
+        return resample(config.resample_filter, &coarse, rows, cols, step);
+    }
+
+    (0..rows)
+        .map(|i| upscale_row(&coarse[i / step], cols, step))
+        .collect()
+}
+
+/// Weight of a sample `d` samples away from the point being filtered.
+fn kernel(filter: Resample, d: f64) -> f64 {
+    let sinc = |x: f64| {
+        if x == 0.0 {
+            1.0
+        } else {
+            (PI * x).sin() / (PI * x)
+        }
+    };
+    match filter {
+        Resample::Nearest => f64::from(d.abs() < 0.5),
+        Resample::Bilinear => (1.0 - d.abs()).max(0.0),
+        Resample::Lanczos if d.abs() < LANCZOS_LOBES => sinc(d) * sinc(d / LANCZOS_LOBES),
+        Resample::Lanczos => 0.0,
+    }
+}
+
+/// Lobes of the Lanczos kernel either side of its center
+const LANCZOS_LOBES: f64 = 3.0;
+
+/// The samples of a `len` long row that `filter` blends at position `x`, in
+/// samples, with their weights. Samples past the ends repeat the edge.
+fn taps(filter: Resample, x: f64, len: usize) -> Vec<(usize, f64)> {
+    let center = x.floor() as i64;
+    let reach = LANCZOS_LOBES as i64;
+    let taps: Vec<(usize, f64)> = (center - reach + 1..=center + reach)
+        .map(|k| {
+            (
+                k.clamp(0, len as i64 - 1) as usize,
+                kernel(filter, x - k as f64),
+            )
+        })
+        .filter(|&(_, w)| w != 0.0)
+        .collect();
+    let total: f64 = taps.iter().map(|&(_, w)| w).sum();
+    taps.into_iter().map(|(k, w)| (k, w / total)).collect()
+}
+
+/// Scale a grid of samples taken every `step` cells up to `rows` x `cols`
+/// with `filter`, first along the rows and then down the columns.
+fn resample(
+    filter: Resample,
+    coarse: &[Vec<f64>],
+    rows: usize,
+    cols: usize,
+    step: usize,
+) -> Vec<Vec<f64>> {
+    let blend = |values: &dyn Fn(usize) -> f64, x: usize, len: usize| {
+        taps(filter, x as f64 / step as f64, len)
+            .iter()
+            .map(|&(k, w)| values(k) * w)
+            .sum::<f64>()
+    };
+    let wide: Vec<Vec<f64>> = coarse
+        .iter()
+        .map(|row| {
+            (0..cols)
+                .map(|j| blend(&|k| row[k], j, row.len()))
+                .collect()
+        })
+        .collect();
+    (0..rows)
+        .map(|i| {
+            (0..cols)
+                .map(|j| blend(&|k| wide[k][j], i, wide.len()))
+                .collect()
+        })
+        .collect()
+}
+
+/// Sample row `i` of the grid of cells [`generate_field`] actually samples,
+/// every `--downsample` cells apart.
+fn coarse_row(
+    config: &HeatmapConfig,
+    sample: &Sampler,
+    i: usize,
+    rows: usize,
+    cols: usize,
+    t: Option<f64>,
+) -> Vec<f64> {
+    let step = config.downsample as usize;
+
+    // Panning animations slide the 2D noise instead of slicing through time
+    let pan = |t: f64| t * config.scale;
+    let (t, offset_y, offset_x) = match (t, config.animate_axis) {
+        (Some(t), AnimateAxis::X) => (None, config.offset_y, config.offset_x + pan(t)),
+        (Some(t), AnimateAxis::Y) => (None, config.offset_y + pan(t), config.offset_x),
+        _ => (t, config.offset_y, config.offset_x),
+    };
+    let (sin, cos) = config.rotate.to_radians().sin_cos();
+    let (center_y, center_x) = (rows as f64 / 2.0, cols as f64 / 2.0);
+
+    (0..cols.div_ceil(step))
+        .map(|j| {
+            let (dy, dx) = ((i * step) as f64 - center_y, (j * step) as f64 - center_x);
+            let y = center_y + dy * cos - dx * sin;
+            let x = center_x + dy * sin + dx * cos;
+            let (y, x) = if config.transpose { (x, y) } else { (y, x) };
+            sample(y + offset_y, x + offset_x, t)
+        })
+        .collect()
+}
+
+/// Widen a coarse row back to `cols` cells, repeating each value `step` times.
+fn upscale_row(coarse: &[f64], cols: usize, step: usize) -> Vec<f64> {
+    (0..cols).map(|j| coarse[j / step]).collect()
+}
+
+/// Load a rectangular grid of values from `path`.
+///
+/// Files ending in `.json` are read as an array of arrays of numbers, anything
+/// else as CSV with one grid row per line.
+fn load_grid(path: &Path) -> Result<Vec<Vec<f64>>, BannerError> {
+    let content = fs::read_to_string(path).map_err(|e| BannerError::read(path, e))?;
+
+    let is_json = path
+        .extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("json"));
+    let grid: Vec<Vec<f64>> = if is_json {
+        serde_json::from_str(&content).map_err(|e| {
+            BannerError::InvalidConfig(format!("invalid JSON grid in {}: {}", path.display(), e))
+        })?
+    } else {
+        content
+            .lines()
+            .enumerate()
+            .filter(|(_, line)| !line.trim().is_empty())
+            .map(|(n, line)| {
+                line.split(',')
+                    .map(|field| {
+                        field.trim().parse::<f64>().map_err(|_| {
+                            BannerError::InvalidConfig(format!(
+                                "invalid number {:?} on line {} of {}",
+                                field.trim(),
+                                n + 1,
+                                path.display()
+                            ))
+                        })
+                    })
+                    .collect()
+            })
+            .collect::<Result<_, _>>()?
+    };
+
+    let width = grid.first().map_or(0, |row| row.len());
+    if width == 0 {
+        return Err(BannerError::InvalidConfig(format!(
+            "grid in {} is empty",
+            path.display()
+        )));
+    }
+    if let Some(n) = grid.iter().position(|row| row.len() != width) {
+        return Err(BannerError::InvalidConfig(format!(
+            "grid in {} is not rectangular: row {} has {} values, expected {}",
+            path.display(),
+            n + 1,
+            grid[n].len(),
+            width
+        )));
+    }
+    if grid.iter().flatten().any(|v| !v.is_finite()) {
+        return Err(BannerError::InvalidConfig(format!(
+            "grid in {} contains non-finite values",
+            path.display()
+        )));
+    }
+    Ok(grid)
+}
+
+/// Rescale every value in `data` to the range [0, 1].
+///
+/// The endpoints default to the field's own extremes. Fixed endpoints keep
+/// colors comparable between banners, with values outside them clamped.
+fn normalize(data: &mut [Vec<f64>], min_value: Option<f64>, max_value: Option<f64>) {
+    let min_val = min_value.unwrap_or_else(|| {
+        data.iter()
+            .flat_map(|row| row.iter())
+            .cloned()
+            .fold(f64::INFINITY, f64::min)
+    });
+    let max_val = max_value.unwrap_or_else(|| {
+        data.iter()
+            .flat_map(|row| row.iter())
+            .cloned()
+            .fold(f64::NEG_INFINITY, f64::max)
+    });
+
+    // A flat field has no range to stretch, so map it all to the bottom color
+    let range = max_val - min_val;
+    for row in data.iter_mut() {
+        for val in row.iter_mut() {
+            *val = if range > 0.0 {
+                (val.clamp(min_val, max_val) - min_val) / range
+            } else {
+                0.0
+            };
+        }
+    }
+}
+
+/// Replace every value in `data` with its rank among all values, scaled to
+/// [0, 1], so the values end up evenly spread.
+///
+/// Equal values share the middle of their ranks.
+fn equalize(data: &mut [Vec<f64>]) {
+    let mut sorted: Vec<f64> = data.iter().flatten().cloned().collect();
+    sorted.sort_by(f64::total_cmp);
+    let last = sorted.len().saturating_sub(1) as f64;
+    for val in data.iter_mut().flatten() {
+        let below = sorted.partition_point(|&v| v < *val);
+        let through = sorted.partition_point(|&v| v <= *val);
+        *val = if last > 0.0 {
+            (below + through - 1) as f64 / 2.0 / last
+        } else {
+            0.0
+        };
+    }
+}
+
+/// Most values sorted to estimate --noise-clamp percentiles
+const PERCENTILE_SAMPLES: usize = 10_000;
+
+/// Clamp raw values to the range between `lo` and `hi`, so a few extreme
+/// cells can't stretch the normalization and wash out the rest.
+///
+/// Percentiles are read from an evenly strided sample of the field, which
+/// is plenty for picking a contrast range and keeps large fields cheap.
+fn clamp_outliers(data: &mut [Vec<f64>], lo: ClampBound, hi: ClampBound) {
+    let values: Vec<f64> = data.iter().flatten().cloned().collect();
+    if values.is_empty() {
+        return;
+    }
+    let stride = values.len().div_ceil(PERCENTILE_SAMPLES);
+    let mut sample: Vec<f64> = values.iter().step_by(stride).cloned().collect();
+    sample.sort_by(f64::total_cmp);
+
+    let resolve = |bound| match bound {
+        ClampBound::Absolute(v) => v,
+        ClampBound::Percentile(p) => {
+            let rank = (p / 100.0 * (sample.len() - 1) as f64).round() as usize;
+            sample[rank]
+        }
+    };
+    let (lo, hi) = (resolve(lo), resolve(hi));
+    let (lo, hi) = (lo.min(hi), lo.max(hi));
+    for val in data.iter_mut().flatten() {
+        *val = val.clamp(lo, hi);
+    }
+}
+
+/// A grayscale image shaping the field, from --noise-mask
+pub(crate) struct Mask(GrayImage);
+
+impl Mask {
+    pub(crate) fn load(path: &Path) -> Result<Self, BannerError> {
+        let image = picture::open(path, "mask")?;
+        Ok(Mask(image.to_luma8()))
+    }
+
+    /// Scale every normalized value by the brightness of the mask over its
+    /// cell, so black areas drop to the bottom of the gradient.
+    ///
+    /// The mask is stretched over the whole grid with bilinear sampling,
+    /// whatever its own aspect ratio.
+    fn apply(&self, data: &mut [Vec<f64>]) {
+        let rows = data.len() as u32;
+        let cols = data.first().map_or(0, |row| row.len()) as u32;
+        if rows == 0 || cols == 0 {
+            return;
+        }
+        let resized = imageops::resize(&self.0, cols, rows, FilterType::Triangle);
+        for (i, row) in data.iter_mut().enumerate() {
+            for (j, val) in row.iter_mut().enumerate() {
+                *val *= resized.get_pixel(j as u32, i as u32)[0] as f64 / 255.0;
+            }
+        }
+    }
+}
+
+/// Map a value in [0, 1] through an easing curve, keeping both ends fixed.
+fn ease(curve: ValueCurve, v: f64) -> f64 {
+    match curve {
+        ValueCurve::Linear => v,
+        ValueCurve::EaseIn => v * v,
+        ValueCurve::EaseOut => 1.0 - (1.0 - v) * (1.0 - v),
+        ValueCurve::EaseInOut if v < 0.5 => 2.0 * v * v,
+        ValueCurve::EaseInOut => 1.0 - 2.0 * (1.0 - v) * (1.0 - v),
+        ValueCurve::Smoothstep => v * v * (3.0 - 2.0 * v),
+    }
+}
+
+/// Normalized value above which cells start to glow
+const GLOW_THRESHOLD: f64 = 0.6;
+
+/// Mean of each value with its neighbours up to `radius` cells away along one
+/// axis, with the window cut short at the edges.
+fn box_blur_1d(values: &[f64], radius: usize) -> Vec<f64> {
+    (0..values.len())
+        .map(|k| {
+            let window = &values[k.saturating_sub(radius)..(k + radius + 1).min(values.len())];
+            window.iter().sum::<f64>() / window.len() as f64
+        })
+        .collect()
+}
+
+/// Blur the part of every normalized value above [`GLOW_THRESHOLD`] with a
+/// box of `radius` cells and add it back, so bright areas bleed into their
+/// surroundings.
+fn glow(data: &mut [Vec<f64>], radius: usize) {
+    let bright: Vec<Vec<f64>> = data
+        .iter()
+        .map(|row| {
+            let row: Vec<f64> = row.iter().map(|v| (v - GLOW_THRESHOLD).max(0.0)).collect();
+            box_blur_1d(&row, radius)
+        })
+        .collect();
+
+    let cols = bright.first().map_or(0, |row| row.len());
+    for j in 0..cols {
+        let column: Vec<f64> = bright.iter().map(|row| row[j]).collect();
+        for (row, halo) in data.iter_mut().zip(box_blur_1d(&column, radius)) {
+            row[j] = (row[j] + halo).min(1.0);
+        }
+    }
+}
+
+/// Percentiles --smooth-normalize maps to the ends of the gradient
+const SMOOTH_PERCENTILES: (f64, f64) = (1.0, 99.0);
+
+/// Normalize a raw field and apply the value transforms selected by `config`.
+pub(crate) fn postprocess(config: &HeatmapConfig, mask: Option<&Mask>, data: &mut [Vec<f64>]) {
+    if let Some(bounds) = &config.noise_clamp {
+        clamp_outliers(data, bounds[0], bounds[1]);
+    } else if config.smooth_normalize {
+        let (lo, hi) = SMOOTH_PERCENTILES;
+        clamp_outliers(data, ClampBound::Percentile(lo), ClampBound::Percentile(hi));
+    }
+    if config.histogram_equalize {
+        equalize(data);
+    } else if config.normalize_per_row {
+        for row in data.iter_mut() {
+            normalize(std::slice::from_mut(row), None, None);
+        }
+    } else {
+        normalize(data, config.min_value, config.max_value);
+    }
+
+    if let Some(mask) = mask {
+        mask.apply(data);
+    }
+
+    if config.value_curve != ValueCurve::Linear {
+        for val in data.iter_mut().flatten() {
+            *val = ease(config.value_curve, *val);
+        }
+    }
+
+    if let Some(radius) = config.glow {
+        glow(data, radius as usize);
+    }
+
+    if let Some(levels) = config.quantize_value {
+        let steps = (levels - 1) as f64;
+        for val in data.iter_mut().flatten() {
+            *val = (*val * steps).round() / steps;
+        }
+    }
+}
+
+/// Build the raw field, either from `--from-grid` or by sampling noise.
+///
+/// `t` selects a slice along the time axis of the noise when animating.
+pub(crate) fn load_field(
+    config: &HeatmapConfig,
+    t: Option<f64>,
+) -> Result<Vec<Vec<f64>>, BannerError> {
+    match &config.from_grid {
+        Some(path) => load_grid(path),
+        None => {
+            let rows = config.rows.unwrap_or_default();
+            let cols = output_width(config.width);
+            match config.repeat_noise {
+                Some(n) => {
+                    let n = n as usize;
+                    let sample = sampler(config, n, n)?;
+                    let tile = generate_field(config, &sample, n, n, t);
+                    Ok((0..rows)
+                        .map(|i| (0..cols).map(|j| tile[i % n][j % n]).collect())
+                        .collect())
+                }
+                None => {
+                    let sample = sampler(config, rows, cols)?;
+                    Ok(generate_field(config, &sample, rows, cols, t))
+                }
+            }
+        }
+    }
+}
+
+/// Shift the offsets in `config` so the brightest cell of `data`, a raw
+/// field sampled with them, moves to row `y` and column `x`.
+pub(crate) fn center_brightest(config: &mut HeatmapConfig, data: &[Vec<f64>], y: f64, x: f64) {
+    let brightest = data
+        .iter()
+        .enumerate()
+        .flat_map(|(i, row)| row.iter().enumerate().map(move |(j, &v)| (i, j, v)))
+        .max_by(|a, b| a.2.total_cmp(&b.2));
+    if let Some((i, j, _)) = brightest {
+        config.offset_y += i as f64 - y;
+        config.offset_x += j as f64 - x;
+    }
+}
+
+/// The seed of the --text-seed field, if there is one. Left without a value,
+/// it follows on from the banner's own seed, so both stay reproducible.
+pub(crate) fn text_seed(config: &HeatmapConfig) -> Option<u64> {
+    let base = config.random.unwrap_or(DEFAULT_SEED);
+    config
+        .text_seed
+        .map(|seed| seed.unwrap_or(base.wrapping_add(1)))
+}
+
+/// Rows of a field, produced one at a time
+pub(crate) type Rows<'a> = Box<dyn Iterator<Item = Vec<f64>> + 'a>;
+
+/// The raw field row by row, for streaming output, along with its height.
+///
+/// Noise rows are sampled only as the iterator reaches them, so the whole
+/// field is never held at once. Grid files and --repeat-noise tiles are
+/// small enough to load up front.
+pub(crate) fn field_rows(config: &HeatmapConfig) -> Result<(usize, Rows<'_>), BannerError> {
+    if config.from_grid.is_some() || config.repeat_noise.is_some() {
+        let data = load_field(config, None)?;
+        return Ok((data.len(), Box::new(data.into_iter())));
+    }
+
+    let rows = config.rows.unwrap_or_default();
+    let cols = output_width(config.width);
+    let sample = sampler(config, rows, cols)?;
+    let step = config.downsample as usize;
+    let mut coarse: Option<(usize, Vec<f64>)> = None;
+    let iter = (0..rows).map(move |i| {
+        // Rows in the same --downsample block share one sampled row
+        let ci = i / step;
+        if coarse.as_ref().is_none_or(|(k, _)| *k != ci) {
+            coarse = Some((ci, coarse_row(config, &sample, ci, rows, cols, None)));
+        }
+        let (_, row) = coarse.as_ref().unwrap();
+        upscale_row(row, cols, step)
+    });
+    Ok((rows, Box::new(iter)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::Parser;
+
+    fn field_with(args: &[&str]) -> Vec<Vec<f64>> {
+        let config = HeatmapConfig::parse_from(["banner", "4", "-w", "20"].iter().chain(args));
+        load_field(&config, None).unwrap()
+    }
+
+    #[test]
+    fn seeds_change_the_field() {
+        assert_ne!(field_with(&["-r", "1"]), field_with(&["-r", "2"]));
+    }
+
+    #[test]
+    fn high_seed_bits_change_the_field() {
+        let high = (1u64 << 32) + 1;
+        assert_ne!(
+            field_with(&["-r", "1"]),
+            field_with(&["-r", &high.to_string()])
+        );
+    }
+}
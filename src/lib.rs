@@ -0,0 +1,548 @@
+//! Heatmap banners: fractal Perlin noise colored with a gradient, with
+//! figlet text laid over the top.
+//!
+//! ```no_run
+//! use banner::{Banner, HeatmapConfig};
+//! use clap::Parser;
+//!
+//! let config = HeatmapConfig::parse_from(["banner", "8", "--text", "hello"]);
+//! let mut banner = Banner::new(config)?;
+//! print!("{}", banner.render()?);
+//! # Ok::<(), banner::BannerError>(())
+//! ```
+
+pub mod color;
+mod config;
+pub mod error;
+mod export;
+mod expr;
+mod field;
+mod picture;
+pub mod profile;
+mod render;
+mod symbol;
+mod text;
+pub mod theme;
+
+pub use config::{
+    AnimateAxis, ClampBound, Direction, Extrusion, FadeDirection, FieldKind, HeatmapConfig,
+    NoiseKind, Overflow, Resample, ValueCurve, VerticalAlign,
+};
+pub use error::BannerError;
+pub use field::{output_width, DEFAULT_SEED};
+
+use color::{ColorMode, Painter};
+use palette::{Gradient, LinSrgb};
+use rand::rngs::StdRng;
+use rand::{random, SeedableRng};
+use std::fs;
+use std::io::Write;
+use std::slice;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// Coarsest sampling --render-budget falls back to
+const MAX_BUDGET_DOWNSAMPLE: u64 = 8;
+
+/// A configured banner, ready to render
+pub struct Banner {
+    config: HeatmapConfig,
+    gradient: Gradient<LinSrgb<f64>>,
+    figure: String,
+    ramp: Vec<String>,
+    mask: Option<field::Mask>,
+    /// Height of the text before it was fitted into the banner
+    full_height: usize,
+    /// Figures of the --text-sequence texts
+    sequence: Vec<String>,
+    /// The --max-colors palette of the last render
+    palette: Vec<(u8, u8, u8)>,
+    /// --palette-reverse-per-octave bands of the last field sampled
+    bands: Option<Vec<Vec<bool>>>,
+    painter: Painter,
+    rng: StdRng,
+}
+
+impl Banner {
+    /// Validate `config`, resolve its theme and draw its text with figlet.
+    pub fn new(mut config: HeatmapConfig) -> Result<Self, BannerError> {
+        if let Some(path) = &config.text_file {
+            let text = fs::read_to_string(path).map_err(|e| BannerError::read(path, e))?;
+            // A final newline would otherwise add an empty row of letters
+            let text = text.strip_suffix('\n').unwrap_or(&text);
+            config.text = Some(text.strip_suffix('\r').unwrap_or(text).to_string());
+        }
+        if config.word_per_line {
+            if let Some(text) = &config.text {
+                config.text = Some(text.split_whitespace().collect::<Vec<_>>().join("\n"));
+            }
+        }
+        if let Some(texts) = &config.text_sequence {
+            config.text = texts.first().cloned();
+        }
+        if let Some(window) = config.seed_from_time {
+            let now = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default();
+            config.random = Some(now.as_secs() / window);
+        }
+        if config.auto_octaves {
+            config.octaves = field::auto_octaves(config.scale, config.lacunarity);
+        }
+
+        field::check_sampling(&config)?;
+        if let (Some(min), Some(max)) = (config.min_value, config.max_value) {
+            if min >= max {
+                return Err(BannerError::InvalidConfig(
+                    "--min-value must be less than --max-value".to_string(),
+                ));
+            }
+        }
+
+        let mut themes = theme::builtin();
+        if let Some(path) = &config.theme_file {
+            themes.extend(theme::load_theme_file(path)?);
+        }
+        let unknown = |name: &str| {
+            let mut names: Vec<_> = themes.keys().cloned().collect();
+            names.sort();
+            BannerError::InvalidConfig(format!(
+                "unknown theme {:?}, available themes: {}",
+                name,
+                names.join(", ")
+            ))
+        };
+        let mut stops = match &config.theme_from_image {
+            Some(path) => theme::image_stops(path)?,
+            None => themes
+                .get(&config.theme)
+                .cloned()
+                .ok_or_else(|| unknown(&config.theme))?,
+        };
+        if let Some(name) = &config.theme2 {
+            let other = themes.get(name).ok_or_else(|| unknown(name))?;
+            stops = theme::blend(&stops, other, config.theme_blend);
+        }
+        if config.grayscale {
+            // A single black to white segment maps each value v straight to
+            // the gray v * 255
+            stops = vec![LinSrgb::new(0.0, 0.0, 0.0), LinSrgb::new(1.0, 1.0, 1.0)];
+        }
+
+        let ramp = match &config.charset_file {
+            Some(path) => text::load_ramp(path)?,
+            None => text::GLYPH_RAMP.iter().map(|s| s.to_string()).collect(),
+        };
+
+        let mask = config
+            .noise_mask
+            .as_deref()
+            .map(field::Mask::load)
+            .transpose()?;
+
+        let rows = config.rows.unwrap_or_default();
+        let figure = match &config.symbol {
+            Some(symbol) => symbol::symbol_figure(symbol, rows)?,
+            None => text::figlet_text(&config)?,
+        };
+        let figure = text::space_rows(&figure, config.line_height);
+        // Figures end with two blank rows that aren't part of the letters
+        let full_height = figure.lines().count().saturating_sub(2);
+        let figure = text::fit_figure(&figure, rows, config.overflow);
+
+        // Every text of a sequence is drawn up front, so the animation only
+        // swaps between them
+        let mut sequence = Vec::new();
+        for text in config.text_sequence.iter().flatten() {
+            let mut step = config.clone();
+            step.text = Some(text.clone());
+            let figure = text::space_rows(&text::figlet_text(&step)?, config.line_height);
+            sequence.push(text::fit_figure(&figure, rows, config.overflow));
+        }
+        text::center_figures(&mut sequence);
+        let figure = sequence.first().cloned().unwrap_or(figure);
+
+        if config.center_on_text {
+            let figlet_lines: Vec<&str> = figure.lines().collect();
+            let layout = render::Layout::new(&config, rows, &figlet_lines);
+            if let Some((y, x)) = layout.text_center(config.glyph_threshold) {
+                let data = field::load_field(&config, None)?;
+                field::center_brightest(&mut config, &data, y, x);
+            }
+        }
+
+        Ok(Self {
+            gradient: Gradient::new(stops),
+            figure,
+            ramp,
+            mask,
+            full_height,
+            sequence,
+            palette: Vec::new(),
+            bands: None,
+            painter: Painter::new(if config.reproducible_colors {
+                ColorMode::Truecolor
+            } else {
+                config.color_mode
+            }),
+            rng: StdRng::seed_from_u64(fade_seed(&config)),
+            config,
+        })
+    }
+
+    pub fn config(&self) -> &HeatmapConfig {
+        &self.config
+    }
+
+    /// Width of the overlay text in columns.
+    pub fn text_width(&self) -> usize {
+        self.figure
+            .lines()
+            .map(|line| line.chars().count())
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// Height of the overlay text in rows.
+    pub fn text_height(&self) -> usize {
+        // Figures end with two blank rows that aren't part of the letters
+        self.figure.lines().count().saturating_sub(2)
+    }
+
+    /// Height of the overlay text as drawn, before any --overflow fitting.
+    pub fn full_text_height(&self) -> usize {
+        self.full_height
+    }
+
+    /// The raw, unnormalized field behind the banner.
+    ///
+    /// `t` selects a slice along the time axis of the noise when animating.
+    /// With --palette-reverse-per-octave the strongest octave of each cell is
+    /// found too, for the next render to color by. With --text-seed the
+    /// letters are sampled from a second field.
+    pub fn field(&mut self, t: Option<f64>) -> Result<Vec<Vec<f64>>, BannerError> {
+        if self.config.palette_reverse_per_octave {
+            self.bands = Some(field::octave_bands(&self.config, t));
+        }
+        let mut data = field::load_field(&self.config, t)?;
+
+        // The letters are cut from their own field
+        if let Some(seed) = field::text_seed(&self.config) {
+            let mut letters = self.config.clone();
+            letters.random = Some(seed);
+            let text_field = field::load_field(&letters, t)?;
+            let figlet_lines: Vec<&str> = self.figure.lines().collect();
+            let layout = render::Layout::new(&self.config, data.len(), &figlet_lines);
+            for (i, (row, text_row)) in data.iter_mut().zip(&text_field).enumerate() {
+                for (j, (val, &text_val)) in row.iter_mut().zip(text_row).enumerate() {
+                    if layout.is_letter(i, j, self.config.glyph_threshold) {
+                        *val = text_val;
+                    }
+                }
+            }
+        }
+        Ok(data)
+    }
+
+    /// Rescale a raw field from [`Banner::field`] to [0, 1] and apply the
+    /// configured value transforms.
+    pub fn normalize(&self, data: &mut [Vec<f64>]) {
+        field::postprocess(&self.config, self.mask.as_ref(), data);
+    }
+
+    /// Normalize a raw field from [`Banner::field`] and render it.
+    pub fn render_field(&mut self, mut data: Vec<Vec<f64>>) -> String {
+        self.normalize(&mut data);
+        self.render_normalized(&data)
+    }
+
+    /// Render a field already passed through [`Banner::normalize`].
+    pub fn render_normalized(&mut self, data: &[Vec<f64>]) -> String {
+        let figlet_lines: Vec<&str> = self.figure.lines().collect();
+        let mut cells = render::cells(
+            &self.config,
+            data,
+            self.bands.as_deref(),
+            &figlet_lines,
+            &self.ramp,
+            &self.gradient,
+            &mut self.rng,
+        );
+        self.palette = render::quantize(&self.config, &mut cells);
+        if self.config.strip_ansi {
+            return render::plain(&cells);
+        }
+        let frame = render::paint(&cells, &mut self.painter);
+        if self.config.at_row.is_none() && self.config.at_col.is_none() {
+            return frame;
+        }
+        render::place(
+            &frame,
+            self.config.at_row.unwrap_or(1),
+            self.config.at_col.unwrap_or(1),
+        )
+    }
+
+    /// Render the banner straight to `out`, one row at a time.
+    ///
+    /// Each row is sampled, normalized, colored and written before the next
+    /// one is started, so output begins at once and the whole field is never
+    /// held in memory. Rows are normalized on their own, which takes fixed
+    /// endpoints or per-row normalization to match the full render.
+    pub fn stream<W: Write>(&mut self, out: &mut W) -> Result<(), BannerError> {
+        let fixed = self.config.min_value.is_some() && self.config.max_value.is_some();
+        if !fixed && !self.config.normalize_per_row {
+            return Err(BannerError::InvalidConfig(
+                "--stream needs --min-value and --max-value, or --normalize-per-row".to_string(),
+            ));
+        }
+
+        let (rows, data) = field::field_rows(&self.config)?;
+        let figlet_lines: Vec<&str> = self.figure.lines().collect();
+        let layout = render::Layout::new(&self.config, rows, &figlet_lines);
+        for (i, mut row) in data.enumerate().take(layout.out_rows) {
+            field::postprocess(&self.config, None, slice::from_mut(&mut row));
+            let row = render::fade_row(&self.config, i, &row, rows, &mut self.rng);
+            let cells = [layout.row(&self.config, i, &row, None, &self.ramp, &self.gradient)];
+            let line = if self.config.strip_ansi {
+                render::plain(&cells)
+            } else {
+                render::paint(&cells, &mut self.painter)
+            };
+            out.write_all(line.as_bytes())?;
+        }
+        Ok(())
+    }
+
+    /// Normalize a raw field from [`Banner::field`] and encode it as a PNG,
+    /// sized by the configured pixel aspect and image size.
+    pub fn render_png(&mut self, mut data: Vec<Vec<f64>>) -> Result<Vec<u8>, BannerError> {
+        self.normalize(&mut data);
+        let mut cells = cells(
+            &self.config,
+            &self.figure,
+            &self.ramp,
+            &self.gradient,
+            &mut self.rng,
+            &data,
+            self.bands.as_deref(),
+        );
+        self.palette = render::quantize(&self.config, &mut cells);
+        let alpha = alpha(&self.config, &self.figure, &data);
+        export::png(
+            &cells,
+            alpha.as_deref(),
+            self.config.pixel_aspect,
+            self.config.png_size,
+            self.config.resample_filter,
+        )
+    }
+
+    /// Normalize a raw field from [`Banner::field`] and encode it as a Sixel
+    /// image, sized like [`Banner::render_png`].
+    pub fn render_sixel(&mut self, mut data: Vec<Vec<f64>>) -> Result<String, BannerError> {
+        self.normalize(&mut data);
+        let mut cells = cells(
+            &self.config,
+            &self.figure,
+            &self.ramp,
+            &self.gradient,
+            &mut self.rng,
+            &data,
+            self.bands.as_deref(),
+        );
+        self.palette = render::quantize(&self.config, &mut cells);
+        export::sixel(
+            &cells,
+            self.config.pixel_aspect,
+            self.config.png_size,
+            self.config.resample_filter,
+        )
+    }
+
+    /// Normalize a raw field from [`Banner::field`] and describe each of its
+    /// cells as JSON, see --emit-json-cells.
+    pub fn render_json_cells(&mut self, mut data: Vec<Vec<f64>>) -> String {
+        self.normalize(&mut data);
+        let mut cells = cells(
+            &self.config,
+            &self.figure,
+            &self.ramp,
+            &self.gradient,
+            &mut self.rng,
+            &data,
+            self.bands.as_deref(),
+        );
+        self.palette = render::quantize(&self.config, &mut cells);
+        export::json_cells(&cells, &data)
+    }
+
+    /// Normalize a raw field from [`Banner::field`] and encode it as a PNG
+    /// displayed through the Kitty graphics protocol.
+    pub fn render_kitty(&mut self, data: Vec<Vec<f64>>) -> Result<String, BannerError> {
+        Ok(export::kitty(&self.render_png(data)?))
+    }
+
+    /// Render the banner as escape-coded text.
+    pub fn render(&mut self) -> Result<String, BannerError> {
+        let data = self.field(None)?;
+        Ok(self.render_field(data))
+    }
+
+    /// Render the banner as raw bytes, for sockets and other sinks that
+    /// don't deal in strings.
+    ///
+    /// The bytes are the same UTF-8 text as [`Banner::render`], ANSI escape
+    /// sequences included.
+    pub fn to_ansi_bytes(&mut self) -> Result<Vec<u8>, BannerError> {
+        Ok(self.render()?.into_bytes())
+    }
+
+    /// Render successive animation frames, passing each to `on_frame` with
+    /// its index until it returns `false`.
+    ///
+    /// A --text-sequence moves on to its next text every --text-hold frames,
+    /// --animate-text-reveal types the text out --reveal-speed columns a frame,
+    /// and frames over the --render-budget lower the quality of the next.
+    ///
+    /// Frames are rendered as fast as `on_frame` returns, so the caller
+    /// controls pacing and where frames go. Time follows the wall clock,
+    /// scaled by the configured speed, so the morph looks the same however
+    /// long each frame takes.
+    pub fn animate<F>(&mut self, mut on_frame: F) -> Result<(), BannerError>
+    where
+        F: FnMut(usize, &str) -> bool,
+    {
+        let start = Instant::now();
+        let full = self.figure.clone();
+        for index in 0.. {
+            // Looping animations step through the loop frame by frame
+            let t = if self.config.loop_seamless_time {
+                (index as u64 % self.config.loop_frames) as f64 / self.config.loop_frames as f64
+            } else {
+                start.elapsed().as_secs_f64() * self.config.speed
+            };
+            // A reveal starts over with each text of a sequence
+            let (figure, shown_for) = if self.sequence.is_empty() {
+                (&full, index as u64)
+            } else {
+                let step = index as u64 / self.config.text_hold;
+                let k = (step % self.sequence.len() as u64) as usize;
+                (&self.sequence[k], index as u64 % self.config.text_hold)
+            };
+            if self.config.animate_text_reveal {
+                let columns = (shown_for + 1).saturating_mul(self.config.reveal_speed);
+                self.figure = text::reveal(figure, columns as usize);
+            } else if !self.sequence.is_empty() {
+                self.figure.clone_from(figure);
+            }
+            let drawing = Instant::now();
+            let data = self.field(Some(t))?;
+            let frame = self.render_field(data);
+            if let Some(budget) = self.config.render_budget {
+                if drawing.elapsed() > Duration::from_millis(budget) {
+                    self.lower_quality();
+                }
+            }
+            if !on_frame(index, &frame) {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    /// Step the quality down for --render-budget: one octave less, or once
+    /// down to one octave, a coarser --downsample.
+    fn lower_quality(&mut self) {
+        if self.config.octaves > 1 {
+            self.config.octaves -= 1;
+        } else if self.config.downsample < MAX_BUDGET_DOWNSAMPLE {
+            self.config.downsample += 1;
+        }
+    }
+
+    /// Number of distinct colors emitted by everything rendered so far.
+    pub fn color_count(&self) -> usize {
+        self.painter.color_count()
+    }
+
+    /// The colors --max-colors picked for the last render, empty without it.
+    pub fn palette(&self) -> &[(u8, u8, u8)] {
+        &self.palette
+    }
+
+    /// Draw the gradient from 0 to 1 across the banner width, with labels.
+    pub fn palette_preview(&mut self) -> String {
+        let cols = field::output_width(self.config.width);
+        render::palette_preview(&self.gradient, &mut self.painter, cols, &self.config)
+    }
+}
+
+/// Render the single animation frame of `config` at time `t`.
+///
+/// This builds a fresh [`Banner`] for the frame, so it suits animation loops
+/// that aren't driven by [`Banner::animate`], such as a game loop picking its
+/// own times. Nothing is shared between calls, so frames can be rendered in
+/// parallel from several threads with the same config.
+///
+/// Under --loop-seamless-time, `t` is the phase of the loop, from 0 to 1.
+pub fn render_frame(config: &HeatmapConfig, t: f64) -> Result<String, BannerError> {
+    let mut banner = Banner::new(config.clone())?;
+    let data = banner.field(Some(t))?;
+    Ok(banner.render_field(data))
+}
+
+/// Color a normalized field, with the text laid over it.
+///
+/// This takes the banner's parts separately so the painter stays free to
+/// encode the result.
+fn cells<'a>(
+    config: &'a HeatmapConfig,
+    figure: &'a str,
+    ramp: &'a [String],
+    gradient: &Gradient<LinSrgb<f64>>,
+    rng: &mut StdRng,
+    data: &[Vec<f64>],
+    bands: Option<&[Vec<bool>]>,
+) -> Vec<Vec<render::Cell<'a>>> {
+    let figlet_lines: Vec<&str> = figure.lines().collect();
+    render::cells(config, data, bands, &figlet_lines, ramp, gradient, rng)
+}
+
+/// Seed of the fade jitter: --random, or without it a fresh one each run,
+/// unless --reproducible-colors pins it to the seed the noise falls back to.
+fn fade_seed(config: &HeatmapConfig) -> u64 {
+    match config.random {
+        Some(seed) => seed,
+        None if config.reproducible_colors => field::DEFAULT_SEED,
+        None => random(),
+    }
+}
+
+/// Opacity of each cell under --bg-alpha, or `None` without it. Letters are
+/// opaque, other cells drop out below the threshold or without one fade out
+/// with their normalized value.
+fn alpha(config: &HeatmapConfig, figure: &str, data: &[Vec<f64>]) -> Option<Vec<Vec<u8>>> {
+    let threshold = config.bg_alpha?;
+    let figlet_lines: Vec<&str> = figure.lines().collect();
+    let layout = render::Layout::new(config, data.len(), &figlet_lines);
+    let alpha = data
+        .iter()
+        .enumerate()
+        .map(|(i, row)| {
+            row.iter()
+                .enumerate()
+                .map(|(j, &value)| {
+                    if layout.is_letter(i, j, config.glyph_threshold) {
+                        return u8::MAX;
+                    }
+                    match threshold {
+                        Some(t) if value >= t => u8::MAX,
+                        Some(_) => 0,
+                        None => (value.clamp(0.0, 1.0) * 255.0).round() as u8,
+                    }
+                })
+                .collect()
+        })
+        .collect();
+    Some(alpha)
+}
@@ -1,192 +1,633 @@
-use clap::Parser;
-use figlet_rs::FIGfont;
-use noise::{NoiseFn, Perlin};
-use palette::{Gradient, LinSrgb};
-use rand::rngs::StdRng;
-use rand::{random, Rng, SeedableRng};
+use banner::color::{self, Capability};
+use banner::{output_width, profile, theme, Banner, BannerError, HeatmapConfig, DEFAULT_SEED};
+use clap::parser::ValueSource;
+use clap::{ArgMatches, CommandFactory, FromArgMatches, ValueEnum};
+use std::env;
+use std::ffi::OsString;
+use std::fs;
 use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+use termion::cursor::{self, HideCursor};
+use termion::screen::AlternateScreen;
 use termion::terminal_size;
 
-/// Generate an ASCII heatmap with Perlin noise
-#[derive(Parser, Debug)]
-#[command(author, version, about, long_about = None)]
-struct Args {
-    /// Number of rows
-    rows: usize,
+/// Number of bins in the `--stats` histogram
+const HISTOGRAM_BINS: usize = 10;
 
-    /// Scale
-    #[arg(short, long, default_value_t = 100.0)]
-    scale: f64,
+/// Width of the longest `--stats` histogram bar
+const HISTOGRAM_WIDTH: usize = 40;
 
-    /// Octaves
-    #[arg(short, long, default_value_t = 6)]
-    octaves: usize,
+/// Print the distribution of the raw values in `data` to stderr.
+fn print_stats(data: &[Vec<f64>]) {
+    let values: Vec<f64> = data.iter().flatten().cloned().collect();
+    if values.is_empty() {
+        return;
+    }
+
+    let n = values.len() as f64;
+    let min = values.iter().cloned().fold(f64::INFINITY, f64::min);
+    let max = values.iter().cloned().fold(f64::NEG_INFINITY, f64::max);
+    let mean = values.iter().sum::<f64>() / n;
+    let std_dev = (values.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / n).sqrt();
 
-    /// Persistence
-    #[arg(short, long, default_value_t = 0.5)]
-    persistence: f64,
+    eprintln!("min:     {:.4}", min);
+    eprintln!("max:     {:.4}", max);
+    eprintln!("mean:    {:.4}", mean);
+    eprintln!("std dev: {:.4}", std_dev);
 
-    /// Lacunarity
-    #[arg(short, long, default_value_t = 2.0)]
-    lacunarity: f64,
+    let mut bins = [0usize; HISTOGRAM_BINS];
+    let width = (max - min) / HISTOGRAM_BINS as f64;
+    for v in &values {
+        let bin = if width > 0.0 {
+            (((v - min) / width) as usize).min(HISTOGRAM_BINS - 1)
+        } else {
+            0
+        };
+        bins[bin] += 1;
+    }
 
-    /// Fade factor range
-    #[arg(short, long, default_value_t = 0.1)]
-    fade_factor_range: f64,
+    let tallest = bins.iter().cloned().max().unwrap_or_default();
+    for (k, &count) in bins.iter().enumerate() {
+        let bar = count * HISTOGRAM_WIDTH / tallest;
+        eprintln!(
+            "{:>8.4} | {:<width$} {}",
+            min + k as f64 * width,
+            "#".repeat(bar),
+            count,
+            width = HISTOGRAM_WIDTH
+        );
+    }
+}
 
-    /// Random seed
-    #[arg(short, long)]
-    random: Option<u64>,
+/// Parse the command line, starting from the --profile preset if one is
+/// picked, or the one profile in the --reproduce file, and return the
+/// matches it came from alongside.
+///
+/// The profile's flags are put in front of the real ones, and later flags
+/// override earlier ones, so anything given on the command line wins.
+fn parse_config() -> Result<(HeatmapConfig, ArgMatches), BannerError> {
+    let args: Vec<OsString> = env::args_os().collect();
+    // A first lenient pass finds the profile before the rows are required
+    let early = HeatmapConfig::command()
+        .ignore_errors(true)
+        .get_matches_from(&args);
+    // Anything the lenient pass couldn't read is left for the real parse to
+    // report
+    let reproduce = match early.try_get_one::<PathBuf>("reproduce") {
+        Ok(Some(path)) => Some(path.clone()),
+        _ => None,
+    };
+    let profile_file = match (&reproduce, early.try_get_one::<PathBuf>("profile_file")) {
+        (Some(path), _) => path.clone(),
+        (None, Ok(Some(path))) => path.clone(),
+        _ => PathBuf::from(profile::DEFAULT_PROFILE_FILE),
+    };
+    if let Ok(Some(true)) = early.try_get_one::<bool>("list_profiles") {
+        for name in profile::load_profiles(&profile_file)?.keys() {
+            println!("{}", name);
+        }
+        process::exit(0);
+    }
+    let name = match early.try_get_one::<String>("profile") {
+        Ok(Some(name)) => name.clone(),
+        _ if reproduce.is_some() => String::new(),
+        _ => {
+            let matches = HeatmapConfig::command().get_matches_from(args);
+            let config = HeatmapConfig::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
+            return Ok((config, matches));
+        }
+    };
+
+    let profiles = profile::load_profiles(&profile_file)?;
+    let names: Vec<&str> = profiles.keys().map(String::as_str).collect();
+    // An exported config holds a single profile, whatever it was named
+    let name = match names.as_slice() {
+        [only] if reproduce.is_some() => only.to_string(),
+        _ if reproduce.is_some() => {
+            return Err(BannerError::InvalidConfig(format!(
+                "{} should hold exactly one profile to --reproduce, found: {}",
+                profile_file.display(),
+                names.join(", ")
+            )))
+        }
+        _ => name,
+    };
+    let preset = profiles.get(&name).ok_or_else(|| {
+        BannerError::InvalidConfig(format!(
+            "unknown profile {:?}, available profiles: {}",
+            name,
+            names.join(", ")
+        ))
+    })?;
+    let command = HeatmapConfig::command();
+    let (rows, preset_args) = profile::profile_args(&command, &name, preset)?;
+    let rows_given = early.value_source("rows") == Some(ValueSource::CommandLine);
+
+    let mut merged: Vec<OsString> = args[..1].to_vec();
+    merged.extend(rows.filter(|_| !rows_given).map(OsString::from));
+    merged.extend(preset_args.into_iter().map(OsString::from));
+    merged.extend(args[1..].iter().cloned());
+    let matches = command.args_override_self(true).get_matches_from(merged);
+    let config = HeatmapConfig::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
+    Ok((config, matches))
+}
+
+/// Print every flag in `matches`, defaults included, as a profile named
+/// `name`, with the seed written out as --random and with --snapshot the
+/// width the banner would have now.
+fn export_config(
+    config: HeatmapConfig,
+    matches: &ArgMatches,
+    name: &str,
+) -> Result<(), BannerError> {
+    let mut flags = profile::export_profile(&HeatmapConfig::command(), matches);
+    if config.snapshot {
+        let width = output_width(config.width);
+        flags.insert("width".to_string(), toml::Value::Integer(width as i64));
+    }
+    if flags.remove("seed-from-time").is_some() {
+        let banner = Banner::new(config)?;
+        let seed = banner.config().random.unwrap_or_default();
+        flags.insert("random".to_string(), toml::Value::Integer(seed as i64));
+    }
+    // Without --random the noise falls back to the default seed, and the
+    // fade jitter then draws from it too, rather than from a fresh one
+    flags
+        .entry("random")
+        .or_insert(toml::Value::Integer(DEFAULT_SEED as i64));
 
-    /// Text to overlay
-    #[arg(short, long)]
-    text: String,
+    let mut profiles = toml::Table::new();
+    profiles.insert(name.to_string(), toml::Value::Table(flags));
+    let content = toml::to_string(&profiles)
+        .map_err(|e| BannerError::InvalidConfig(format!("cannot export config: {}", e)))?;
+    print!("{}", content);
+    Ok(())
 }
 
 fn main() {
-    let args = Args::parse();
-
-    let (cols, _) = terminal_size().unwrap_or((80, 20));
-    let cols = cols as usize;
-
-    let mut rng = StdRng::seed_from_u64(args.random.unwrap_or(random()));
-    let perlin = Perlin::new();
-
-    let mut data = vec![vec![0.0; cols]; args.rows];
-    for i in 0..args.rows {
-        for j in 0..cols {
-            let mut val = 0.0;
-            let mut frequency = 1.0;
-            let mut amplitude = 1.0;
-            let mut max_value = 0.0;
-            for _ in 0..args.octaves {
-                val += perlin.get([
-                    i as f64 / args.scale * frequency,
-                    j as f64 / args.scale * frequency,
-                ]) * amplitude;
-                max_value += amplitude;
-                amplitude *= args.persistence;
-                frequency *= args.lacunarity;
+    let (config, matches) = parse_config().unwrap_or_else(|e| {
+        eprintln!("error: {}", e);
+        process::exit(1);
+    });
+
+    let result = match config.export_config.clone() {
+        Some(name) => export_config(config, &matches, &name),
+        None => run(config),
+    };
+    if let Err(e) = result {
+        // A closed pipe (e.g. `banner ... | head`) isn't worth reporting
+        if let BannerError::Io(err) = &e {
+            if err.kind() == io::ErrorKind::BrokenPipe {
+                return;
             }
-            data[i][j] = val / max_value;
-        }
-    }
-
-    let min_val = data
-        .iter()
-        .flat_map(|row| row.iter())
-        .cloned()
-        .fold(f64::INFINITY, f64::min);
-    let max_val = data
-        .iter()
-        .flat_map(|row| row.iter())
-        .cloned()
-        .fold(f64::NEG_INFINITY, f64::max);
-
-    for row in data.iter_mut() {
-        for val in row.iter_mut() {
-            *val = (*val - min_val) / (max_val - min_val);
-        }
-    }
-
-    let gradient = Gradient::new(vec![
-        LinSrgb::new(0.157, 0.165, 0.212), // Final color #282A36
-        LinSrgb::new(0.0, 0.5, 0.7),       // Adjusted light blue
-        LinSrgb::new(0.545, 0.914, 0.992), // #8BE9FD (cyan from Dracula theme)
-        LinSrgb::new(0.7, 0.85, 0.9),      // Adjusted light cyan
-    ]);
-
-    let chars = ["█"];
-    let mut stdout = io::stdout();
-
-    // Generate heatmap
-    let mut heatmap = vec![vec![(0.0, "█"); cols]; args.rows];
-    for (i, row) in data.iter().enumerate() {
-        for (j, &val) in row.iter().enumerate() {
-            let fade_factor = 1.0
-                - (j as f64 / cols as f64)
-                    * (1.0 + rng.gen_range(-args.fade_factor_range..=args.fade_factor_range));
-            let fade_factor = fade_factor.clamp(0.0, 1.0);
-            // let val = val * fade_factor;
-
-            let char_index = (val * (chars.len() - 1) as f64).round() as usize;
-            let ch = chars[char_index];
-
-            let color = gradient.get(val);
-            let (r, g, b) = (
-                (color.red * 255.0) as u8,
-                (color.green * 255.0) as u8,
-                (color.blue * 255.0) as u8,
-            );
+        }
+        eprintln!("error: {}", e);
+        process::exit(1);
+    }
+}
+
+fn run(config: HeatmapConfig) -> Result<(), BannerError> {
+    if config.probe {
+        let capability = Capability::detect();
+        println!("detected: {}", capability);
+        if capability == Capability::Monochrome {
+            println!("warning: colors may not display correctly");
+        }
+        let mode = capability.recommended_mode().to_possible_value().unwrap();
+        println!("recommended: --color-mode {}", mode.get_name());
+        if color::sixel_supported() {
+            println!("sixel: supported, try --sixel");
+        }
+        if color::kitty_supported() {
+            println!("kitty graphics: supported, try --kitty");
+        }
+        return Ok(());
+    }
 
-            heatmap[i][j] = (val, ch);
-            // write!(stdout, "\x1b[38;2;{};{};{}m{}\x1b[0m", r, g, b, ch).unwrap();
+    if config.list_themes {
+        let mut themes = theme::builtin();
+        if let Some(path) = &config.theme_file {
+            themes.extend(theme::load_theme_file(path)?);
+        }
+        let mut names: Vec<_> = themes.keys().collect();
+        names.sort();
+        for name in names {
+            println!("{}", name);
         }
-        // writeln!(stdout).unwrap();
+        return Ok(());
     }
 
-    // Overlay figlet text with stretched background
-    let standard_font = FIGfont::from_content(include_str!("../font.flf")).unwrap();
-    let figure = standard_font.convert(&args.text).unwrap();
-    let string = figure.to_string();
-    let figlet_lines: Vec<&str> = string.lines().collect();
-    let figlet_height = figlet_lines.len();
+    if config.stdin_lines {
+        return batch(config);
+    }
 
-    for (i, &line) in figlet_lines.iter().enumerate() {
-        if i >= figlet_height - 2 {
-            continue;
+    if config.compare_octaves.is_some() || config.compare_scale.is_some() {
+        return compare(config);
+    }
+
+    if config.seed_grid.is_some() {
+        return seed_grid(config);
+    }
+
+    if config.tile_output.is_some() {
+        return tile_output(config);
+    }
+
+    let mut banner = Banner::new(config)?;
+
+    if banner.config().measure_text {
+        println!("{}x{}", banner.text_width(), banner.text_height());
+        return Ok(());
+    }
+
+    // A picked seed is echoed so the banner can be made again with --random
+    let config = banner.config();
+    if (config.seed_from_time.is_some() && !config.quiet_seed) || config.verbose {
+        match config.random {
+            Some(seed) => eprintln!("seed: {}", seed),
+            None => eprintln!("seed: default"),
         }
-        for j in 0..cols {
-            if i < args.rows {
-                let (val, _) = heatmap[i][j];
-                let color = gradient.get(val);
-                let (mut r, mut g, mut b) = (
-                    (color.red * 255.0) as u8,
-                    (color.green * 255.0) as u8,
-                    (color.blue * 255.0) as u8,
-                );
+    }
+
+    if banner.config().verbose && banner.config().auto_octaves {
+        eprintln!("octaves: {}", banner.config().octaves);
+    }
 
-                let ch = if j < line.len() {
-                    line.chars().nth(j).unwrap_or(' ')
-                } else {
-                    ' '
-                };
-
-                // write!(stdout, "\x1b[48;2;{};{};{}m{}\x1b[0m", r, g, b, ch).unwrap();
-                // Calculate the luminance of the background color
-                let luminance = 0.299 * r as f32 + 0.587 * g as f32 + 0.114 * b as f32;
-
-                // Determine the text color based on the luminance
-                let (text_r, text_g, text_b) = if luminance > 128.0 {
-                    (0, 0, 0) // Bright background, use black text
-                } else {
-                    (255, 255, 255) // Dark background, use white text
-                };
-
-                if ch != ' ' {
-                    r = (0.4 * r as f32).round() as u8;
-                    g = (0.4 * g as f32).round() as u8;
-                    b = (0.4 * b as f32).round() as u8;
+    let cols = output_width(banner.config().width);
+    if banner.config().from_grid.is_none() && banner.text_width() > cols {
+        eprintln!(
+            "warning: the text is {} columns wide but the banner only has {}",
+            banner.text_width(),
+            cols
+        );
+    }
+
+    let rows = banner.config().rows.unwrap_or_default();
+    let stacked = banner.config().word_per_line || banner.config().line_height > 0;
+    if stacked && banner.full_text_height() > rows {
+        eprintln!(
+            "warning: the text is {} rows tall but the banner only has {}",
+            banner.full_text_height(),
+            rows
+        );
+    }
+
+    if banner.config().palette_preview {
+        io::stdout().write_all(banner.palette_preview().as_bytes())?;
+        return Ok(());
+    }
+
+    if banner.config().animate {
+        return animate(&mut banner);
+    }
+
+    if banner.config().stream {
+        return banner.stream(&mut io::stdout().lock());
+    }
+
+    let start = Instant::now();
+    let mut data = banner.field(None)?;
+    let generated = start.elapsed();
+
+    if banner.config().stats {
+        print_stats(&data);
+    }
+
+    // Explicit format flags win over the extension of --output
+    let config = banner.config();
+    let format = if config.png.is_some() {
+        Format::Png
+    } else if config.kitty {
+        Format::Kitty
+    } else if config.sixel {
+        Format::Sixel
+    } else if config.emit_json_cells {
+        Format::JsonCells
+    } else if let Some(path) = &config.output {
+        Format::from_path(path)?
+    } else {
+        Format::Text
+    };
+    let path = config.png.clone().or_else(|| config.output.clone());
+
+    let bytes = match format {
+        Format::Png => banner.render_png(data)?,
+        Format::Kitty => banner.render_kitty(data)?.into_bytes(),
+        Format::Sixel => banner.render_sixel(data)?.into_bytes(),
+        Format::JsonCells => banner.render_json_cells(data).into_bytes(),
+        Format::Text => {
+            let start = Instant::now();
+            banner.normalize(&mut data);
+            let normalized = start.elapsed();
+            let start = Instant::now();
+            let frame = banner.render_normalized(&data);
+            let rendered = start.elapsed();
+
+            if banner.config().verbose {
+                eprintln!("colors: {}", banner.color_count());
+                if !banner.palette().is_empty() {
+                    let hex: Vec<String> = banner
+                        .palette()
+                        .iter()
+                        .map(|(r, g, b)| format!("#{:02x}{:02x}{:02x}", r, g, b))
+                        .collect();
+                    eprintln!("palette: {}", hex.join(" "));
                 }
+                eprintln!(
+                    "time: generate {:.2?}, normalize {:.2?}, render {:.2?}",
+                    generated, normalized, rendered
+                );
+            }
+            frame.into_bytes()
+        }
+    };
+
+    match path {
+        Some(path) => fs::write(&path, bytes)?,
+        None => io::stdout().write_all(&bytes)?,
+    }
+    Ok(())
+}
 
-                // let ch = if ch == ' ' { ' ' } else { '⠿' };
-                // let ch = if ch == ' ' { ' ' } else { '█' };
-                let ch = if ch == ' ' { ' ' } else { ' ' };
-
-                write!(
-                    stdout,
-                    "\x1b[48;2;{};{};{}m\x1b[38;2;{};{};{}m{}\x1b[0m",
-                    r,
-                    g,
-                    b, // Background color
-                    text_r,
-                    text_g,
-                    text_b, // Text color
-                    ch      // Character to print
+/// What the banner is encoded as
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Format {
+    Text,
+    Png,
+    Sixel,
+    Kitty,
+    JsonCells,
+}
+
+impl Format {
+    /// Pick the format for an --output file from its extension.
+    fn from_path(path: &Path) -> Result<Self, BannerError> {
+        let ext = path
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .unwrap_or_default()
+            .to_ascii_lowercase();
+        match &ext[..] {
+            "png" => Ok(Format::Png),
+            "six" | "sixel" => Ok(Format::Sixel),
+            "json" => Ok(Format::JsonCells),
+            "txt" | "ans" => Ok(Format::Text),
+            _ => Err(BannerError::InvalidConfig(format!(
+                "cannot tell the output format of {}, supported extensions: png, six, sixel, json, txt, ans",
+                path.display()
+            ))),
+        }
+    }
+}
+
+/// Render the banner in the left half of the width and a copy with the
+/// --compare-* parameters in the right half.
+///
+/// Both halves sample the noise independently from their own left edge, so
+/// only the parameters differ between them.
+fn compare(config: HeatmapConfig) -> Result<(), BannerError> {
+    let cols = output_width(config.width);
+
+    let mut left = config.clone();
+    left.width = Some(cols / 2);
+    let mut right = config;
+    right.width = Some(cols - cols / 2);
+    if let Some(octaves) = right.compare_octaves {
+        right.octaves = octaves;
+    }
+    if let Some(scale) = right.compare_scale {
+        right.scale = scale;
+    }
+
+    let left = Banner::new(left)?.render()?;
+    let right = Banner::new(right)?.render()?;
+    let mut stdout = io::stdout().lock();
+    for (l, r) in left.lines().zip(right.lines()) {
+        writeln!(stdout, "{}{}", l, r)?;
+    }
+    Ok(())
+}
+
+/// Render a contact sheet of banners with consecutive seeds, tiled across the
+/// width and down the rows, with each tile's seed printed under it.
+fn seed_grid(config: HeatmapConfig) -> Result<(), BannerError> {
+    let (grid_rows, grid_cols) = match config.seed_grid.as_deref() {
+        Some(&[rows, cols]) => (rows as usize, cols as usize),
+        _ => unreachable!("clap takes exactly two values"),
+    };
+    let tile_cols = output_width(config.width) / grid_cols;
+    if tile_cols == 0 {
+        return Err(BannerError::InvalidConfig(
+            "seed grid needs at least one column per tile".to_string(),
+        ));
+    }
+    // Each row of tiles gives up a row to its seed labels
+    let tile_rows = (config.rows.unwrap_or_default() / grid_rows).saturating_sub(1);
+    if tile_rows == 0 {
+        return Err(BannerError::InvalidConfig(
+            "seed grid needs at least two rows per tile, one for its label".to_string(),
+        ));
+    }
+    let base = config.random.unwrap_or_default();
+
+    let mut stdout = io::stdout().lock();
+    for i in 0..grid_rows {
+        let seeds: Vec<u64> = (0..grid_cols)
+            .map(|j| base.wrapping_add((i * grid_cols + j) as u64))
+            .collect();
+        let mut tiles = Vec::with_capacity(grid_cols);
+        for &seed in &seeds {
+            let mut tile = config.clone();
+            tile.width = Some(tile_cols);
+            tile.rows = Some(tile_rows);
+            tile.random = Some(seed);
+            tiles.push(Banner::new(tile)?.render()?);
+        }
+
+        for line in side_by_side(&tiles) {
+            writeln!(stdout, "{}", line)?;
+        }
+        let labels: String = seeds
+            .iter()
+            .map(|seed| {
+                format!(
+                    "{:<width$.width$}",
+                    format!("seed {}", seed),
+                    width = tile_cols
                 )
-                .unwrap();
+            })
+            .collect();
+        writeln!(stdout, "{}", labels.trim_end())?;
+    }
+    Ok(())
+}
+
+/// Repeat the banner as a grid of tiles, cut off where the terminal ends.
+///
+/// Each tile is --width columns wide, or an even share of the available
+/// width without it. Tiles are identical unless --tile-seed-offset moves
+/// each one's seed on from the last. Tiles past the right edge are dropped and the last one
+/// that fits is rendered narrower, keeping its left part.
+fn tile_output(config: HeatmapConfig) -> Result<(), BannerError> {
+    let (nx, ny) = match config.tile_output.as_deref() {
+        Some(&[nx, ny]) => (nx as usize, ny as usize),
+        _ => unreachable!("clap takes exactly two values"),
+    };
+    // Output that isn't going to a terminal is never clipped
+    let (term_cols, term_rows) = terminal_size()
+        .map_or((usize::MAX, usize::MAX), |(cols, rows)| {
+            (cols as usize, rows as usize)
+        });
+    // --width sizes each tile, otherwise the tiles share the available width
+    let tile_cols = config.width.unwrap_or_else(|| output_width(None) / nx);
+    if tile_cols == 0 {
+        return Err(BannerError::InvalidConfig(
+            "tile output needs at least one column per tile".to_string(),
+        ));
+    }
+    let base = config.random.unwrap_or_default();
+
+    let mut stdout = io::stdout().lock();
+    let mut rows_left = term_rows;
+    for i in 0..ny {
+        let mut tiles = Vec::with_capacity(nx);
+        for j in (0..nx).take_while(|j| j * tile_cols < term_cols) {
+            let mut tile = config.clone();
+            tile.width = Some(tile_cols.min(term_cols - j * tile_cols));
+            if config.tile_seed_offset > 0 {
+                let step = (i * nx + j) as u64 * config.tile_seed_offset;
+                tile.random = Some(base.wrapping_add(step));
             }
+            tiles.push(Banner::new(tile)?.render()?);
+        }
+
+        for line in side_by_side(&tiles) {
+            if rows_left == 0 {
+                return Ok(());
+            }
+            writeln!(stdout, "{}", line)?;
+            rows_left -= 1;
+        }
+    }
+    Ok(())
+}
+
+/// Join rendered banners left to right, line by line, stopping at the
+/// shortest.
+fn side_by_side(tiles: &[String]) -> Vec<String> {
+    let mut lines: Vec<_> = tiles.iter().map(|tile| tile.lines()).collect();
+    let mut out = Vec::new();
+    loop {
+        let mut row = String::new();
+        for tile in &mut lines {
+            match tile.next() {
+                Some(line) => row.push_str(line),
+                None => return out,
+            }
+        }
+        out.push(row);
+    }
+}
+
+/// Render a banner for every line of stdin, one after another.
+///
+/// Blank lines are skipped, but still count towards the --seed-sequence
+/// index, so a banner keeps its seed when lines around it are cleared.
+fn batch(config: HeatmapConfig) -> Result<(), BannerError> {
+    let mut stdout = io::stdout().lock();
+    for (n, line) in io::stdin().lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let mut config = config.clone();
+        config.text = Some(line);
+        if let Some(base) = config.seed_sequence {
+            config.random = Some(base.wrapping_add(n as u64));
+        }
+        let frame = Banner::new(config)?.render()?;
+        stdout.write_all(frame.as_bytes())?;
+    }
+    Ok(())
+}
+
+/// Redraw the banner in place until Ctrl-C.
+fn animate(banner: &mut Banner) -> Result<(), BannerError> {
+    // Catch Ctrl-C so the terminal guards below get to restore the screen
+    let running = Arc::new(AtomicBool::new(true));
+    {
+        let running = running.clone();
+        ctrlc::set_handler(move || running.store(false, Ordering::SeqCst))
+            .map_err(|e| BannerError::Io(io::Error::other(e)))?;
+    }
+
+    let alt_screen = banner.config().alt_screen;
+    let interval = Duration::from_secs_f64(1.0 / banner.config().max_fps);
+    let mut dropped = 0;
+    let mut result = Ok(());
+    {
+        let stdout = io::stdout();
+        let out: Box<dyn Write> = if alt_screen {
+            Box::new(AlternateScreen::from(stdout))
+        } else {
+            Box::new(stdout)
+        };
+        let mut out = HideCursor::from(out);
+
+        let mut drawn_rows = 0;
+        let mut draw = |frame: &str| -> io::Result<()> {
+            // Move back over the previous frame to draw on top of it
+            if alt_screen {
+                write!(out, "{}", cursor::Goto(1, 1))?;
+            } else if drawn_rows > 0 {
+                write!(out, "\r{}", cursor::Up(drawn_rows))?;
+            }
+            out.write_all(frame.as_bytes())?;
+            out.flush()?;
+            drawn_rows = frame.lines().count() as u16;
+            Ok(())
+        };
+
+        let mut next_frame: Option<Instant> = None;
+        banner.animate(|_, frame| {
+            if let Err(e) = draw(frame) {
+                result = Err(e);
+                return false;
+            }
+
+            // Sleep until the frame's deadline rather than for a fixed delay,
+            // so time spent rendering doesn't add up. A frame that overruns
+            // skips the deadlines it missed instead of rushing to catch up.
+            let now = Instant::now();
+            let deadline = *next_frame.get_or_insert(now);
+            if now < deadline {
+                thread::sleep(deadline - now);
+                next_frame = Some(deadline + interval);
+            } else {
+                let missed = ((now - deadline).as_secs_f64() / interval.as_secs_f64()) as usize;
+                dropped += missed;
+                next_frame = Some(now + interval);
+            }
+            running.load(Ordering::SeqCst)
+        })?;
+    }
+    if banner.config().verbose {
+        eprintln!("dropped frames: {}", dropped);
+        if banner.config().render_budget.is_some() {
+            eprintln!(
+                "quality: octaves {}, downsample {}",
+                banner.config().octaves,
+                banner.config().downsample
+            );
         }
-        writeln!(stdout).unwrap();
     }
+    io::stdout().flush()?;
+    result?;
+    Ok(())
 }
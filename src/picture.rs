@@ -0,0 +1,49 @@
+//! Reading the images behind --noise-mask and --theme-from-image.
+//!
+//! PNGs are decoded with the same png crate that writes --png output, and
+//! anything else is left to image, which is only built with JPEG support.
+
+use crate::error::BannerError;
+use image::{DynamicImage, ImageBuffer};
+use png::{ColorType, Decoder, Transformations};
+use std::fs;
+use std::path::Path;
+
+const PNG_SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+
+/// Decode the image at `path`, naming it as a `what` image in errors.
+pub(crate) fn open(path: &Path, what: &str) -> Result<DynamicImage, BannerError> {
+    let bytes = fs::read(path).map_err(|e| BannerError::read(path, e))?;
+    let decoded = if bytes.starts_with(PNG_SIGNATURE) {
+        decode_png(&bytes)
+    } else {
+        image::load_from_memory(&bytes).map_err(|e| e.to_string())
+    };
+    decoded.map_err(|e| {
+        BannerError::InvalidConfig(format!("invalid {} image {}: {}", what, path.display(), e))
+    })
+}
+
+fn decode_png(bytes: &[u8]) -> Result<DynamicImage, String> {
+    let mut decoder = Decoder::new(bytes);
+    // Palettes and low bit depths become 8-bit gray or RGB, with tRNS as alpha
+    decoder.set_transformations(Transformations::EXPAND | Transformations::STRIP_16);
+    let mut reader = decoder.read_info().map_err(|e| e.to_string())?;
+    let mut buf = vec![0; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut buf).map_err(|e| e.to_string())?;
+    buf.truncate(info.buffer_size());
+
+    let (width, height) = (info.width, info.height);
+    let image = match info.color_type {
+        ColorType::Grayscale => {
+            ImageBuffer::from_raw(width, height, buf).map(DynamicImage::ImageLuma8)
+        }
+        ColorType::GrayscaleAlpha => {
+            ImageBuffer::from_raw(width, height, buf).map(DynamicImage::ImageLumaA8)
+        }
+        ColorType::Rgb => ImageBuffer::from_raw(width, height, buf).map(DynamicImage::ImageRgb8),
+        ColorType::Rgba => ImageBuffer::from_raw(width, height, buf).map(DynamicImage::ImageRgba8),
+        ColorType::Indexed => None,
+    };
+    image.ok_or_else(|| "unexpected PNG pixel layout".to_string())
+}
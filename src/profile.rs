@@ -0,0 +1,179 @@
+//! Named flag presets, loaded from a TOML file.
+//!
+//! Every table in the file is one profile, mapping long flag names to their
+//! values:
+//!
+//! ```toml
+//! [header]
+//! rows = 8
+//! text = "Welcome"
+//! theme = "nord"
+//! smooth-text = true
+//! ```
+//!
+//! `rows` sets the positional row count. Booleans switch a flag on or off,
+//! and arrays pass several values to flags that take them, like
+//! `noise-clamp = ["2%", "98%"]`.
+//!
+//! Flags given on the command line win over the profile.
+
+use crate::error::BannerError;
+use clap::{Arg, ArgAction, ArgMatches, Command};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+use toml::{Table, Value};
+
+/// Profiles by name
+pub type Profiles = BTreeMap<String, Table>;
+
+/// Profile file read when no other is given
+pub const DEFAULT_PROFILE_FILE: &str = "banner.toml";
+
+/// Load every profile from a TOML file.
+pub fn load_profiles(path: &Path) -> Result<Profiles, BannerError> {
+    let content = fs::read_to_string(path).map_err(|e| BannerError::read(path, e))?;
+    let invalid = |e: String| {
+        BannerError::InvalidConfig(format!("invalid profile file {}: {}", path.display(), e))
+    };
+    let table: Table = toml::from_str(&content).map_err(|e| invalid(e.to_string()))?;
+
+    table
+        .into_iter()
+        .map(|(name, value)| match value {
+            Value::Table(flags) => Ok((name, flags)),
+            _ => Err(invalid(format!("{:?} is not a table of flags", name))),
+        })
+        .collect()
+}
+
+/// Turn a profile into command-line arguments for `command`, returning the
+/// row count separately so it can give way to one on the real command line.
+///
+/// Values equal to a flag's default are left out: they change nothing, and
+/// a full export holds many that would otherwise trip the rules between
+/// flags, like --speed without --animate.
+pub fn profile_args(
+    command: &Command,
+    name: &str,
+    profile: &Table,
+) -> Result<(Option<String>, Vec<String>), BannerError> {
+    let scalar = |key: &str, value: &Value| match value {
+        Value::String(s) => Ok(s.clone()),
+        Value::Integer(n) => Ok(n.to_string()),
+        Value::Float(x) => Ok(x.to_string()),
+        _ => Err(BannerError::InvalidConfig(format!(
+            "unsupported value for {:?} in profile {:?}",
+            key, name
+        ))),
+    };
+
+    let mut rows = None;
+    let mut args = Vec::new();
+    for (key, value) in profile {
+        if key == "rows" {
+            rows = Some(scalar(key, value)?);
+            continue;
+        }
+        let long = key.replace('_', "-");
+        let arg = command
+            .get_arguments()
+            .find(|arg| arg.get_long() == Some(long.as_str()))
+            .ok_or_else(|| {
+                BannerError::InvalidConfig(format!("unknown flag {:?} in profile {:?}", key, name))
+            })?;
+        let flag = format!("--{}", long);
+        let values = match value {
+            Value::Boolean(true) => {
+                // Switches and flags given without their optional value
+                args.push(flag);
+                continue;
+            }
+            Value::Boolean(false) => continue,
+            Value::Array(values) => values
+                .iter()
+                .map(|value| scalar(key, value))
+                .collect::<Result<Vec<_>, _>>()?,
+            value => vec![scalar(key, value)?],
+        };
+        if is_default(arg, &values) {
+            continue;
+        }
+        match values.as_slice() {
+            // Attached with `=`, so negative numbers aren't taken for flags
+            [value] => args.push(format!("{}={}", flag, value)),
+            values => {
+                args.push(flag);
+                args.extend(values.iter().cloned());
+            }
+        }
+    }
+    Ok((rows, args))
+}
+
+/// Whether `values` are the default of `arg`, comparing numbers by value so
+/// `100` matches a default written as `100.0`.
+fn is_default(arg: &Arg, values: &[String]) -> bool {
+    let defaults = arg.get_default_values();
+    defaults.len() == values.len()
+        && defaults.iter().zip(values).all(|(default, value)| {
+            let default = default.to_string_lossy();
+            match (default.parse::<f64>(), value.parse::<f64>()) {
+                (Ok(a), Ok(b)) => a == b,
+                _ => default == value.as_str(),
+            }
+        })
+}
+
+/// Flags that pick, list or export profiles rather than shape the banner
+const PROFILE_FLAGS: [&str; 6] = [
+    "profile",
+    "reproduce",
+    "profile_file",
+    "list_profiles",
+    "export_config",
+    "snapshot",
+];
+
+/// Turn every flag with a value in `matches`, defaults included, into a
+/// profile, the reverse of [`profile_args`]. Flags without a value, which
+/// default to off, are left out.
+pub fn export_profile(command: &Command, matches: &ArgMatches) -> Table {
+    // Numbers are written as numbers so the file reads naturally, apart from
+    // seeds too large for TOML's integers, which would lose digits as floats
+    let scalar = |raw: &str| {
+        if let Ok(n) = raw.parse::<i64>() {
+            Value::Integer(n)
+        } else if raw.parse::<u64>().is_ok() {
+            Value::String(raw.to_string())
+        } else if let Ok(x) = raw.parse::<f64>() {
+            Value::Float(x)
+        } else {
+            Value::String(raw.to_string())
+        }
+    };
+
+    let mut profile = Table::new();
+    for arg in command.get_arguments() {
+        let id = arg.get_id().as_str();
+        if PROFILE_FLAGS.contains(&id) {
+            continue;
+        }
+        let Some(raw) = matches.get_raw(id) else {
+            continue;
+        };
+        let values: Vec<String> = raw.map(|v| v.to_string_lossy().into_owned()).collect();
+        let key = arg
+            .get_long()
+            .map_or_else(|| id.to_string(), |long| long.to_string());
+        let value = match arg.get_action() {
+            ArgAction::SetTrue => Value::Boolean(values.first().is_some_and(|v| v == "true")),
+            // Given without its optional value
+            _ if values.is_empty() => Value::Boolean(true),
+            _ if values.len() > 1 => Value::Array(values.iter().map(|v| scalar(v)).collect()),
+            _ => scalar(&values[0]),
+        };
+        profile.insert(key, value);
+    }
+    profile
+}
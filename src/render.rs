@@ -0,0 +1,583 @@
+use crate::color::{lift_to_luminance, median_cut, nearest, to_rgb, Painter};
+use crate::config::{FadeDirection, HeatmapConfig, VerticalAlign};
+use crate::text::{
+    figlet_char, figlet_str, glyph_coverage, is_filled, is_glyph, letter_columns, PARTIAL_BLOCKS,
+};
+use palette::{Gradient, LinSrgb};
+use rand::rngs::StdRng;
+use rand::Rng;
+use std::fmt::Write;
+use std::iter;
+
+/// Minimum background luminance under --high-contrast.
+///
+/// Against black letters this is a contrast ratio of (0.3 + 0.05) / 0.05 = 7:1,
+/// the WCAG AAA target for normal text.
+const HIGH_CONTRAST_LUMINANCE: f64 = 0.3;
+
+/// Digits shown by --cell-text, one per tenth of the value range
+const DIGITS: [&str; 10] = ["0", "1", "2", "3", "4", "5", "6", "7", "8", "9"];
+
+/// Number of value labels under the palette preview
+const PREVIEW_LABELS: usize = 5;
+
+/// How much letters dim the heatmap color under them
+const TEXT_DARKENING: f32 = 0.4;
+
+/// Dim a heatmap color by `factor` to mark which cells the letters are cut
+/// from.
+fn darken((r, g, b): (u8, u8, u8), factor: f32) -> (u8, u8, u8) {
+    let dim = |c: u8| (factor * c as f32).round() as u8;
+    (dim(r), dim(g), dim(b))
+}
+
+/// The factor the letters darken a heatmap value `val` by.
+fn letter_darkening(config: &HeatmapConfig, val: f64) -> f32 {
+    if config.text_opacity_by_value {
+        // Fade linearly from the bright end of the range to the dark
+        let (bright, dark) = (config.text_darken_range[0], config.text_darken_range[1]);
+        (dark + (bright - dark) * val) as f32
+    } else {
+        TEXT_DARKENING
+    }
+}
+
+/// Distance from every cell of `figlet_lines` within `radius` of a letter to
+/// the nearest filled cell, for --text-mask-feather. Cells further away are
+/// left at infinity.
+fn letter_distances(figlet_lines: &[&str], radius: usize, threshold: f64) -> Vec<Vec<f64>> {
+    let width = figlet_lines
+        .iter()
+        .map(|line| line.chars().count())
+        .max()
+        .unwrap_or(0);
+    let mut distances = vec![vec![f64::INFINITY; width + radius]; figlet_lines.len() + radius];
+    for (i, line) in figlet_lines.iter().enumerate() {
+        for (j, ch) in line.chars().enumerate() {
+            if !is_filled(ch, threshold) {
+                continue;
+            }
+            let (top, left) = (i.saturating_sub(radius), j.saturating_sub(radius));
+            for (row, y) in distances[top..=i + radius].iter_mut().zip(top..) {
+                for (cell, x) in row[left..=j + radius].iter_mut().zip(left..) {
+                    let d = ((y as f64 - i as f64).powi(2) + (x as f64 - j as f64).powi(2)).sqrt();
+                    *cell = cell.min(d);
+                }
+            }
+        }
+    }
+    distances
+}
+
+/// Pass a final color through the --color-blind simulation or correction.
+fn vision(config: &HeatmapConfig, rgb: (u8, u8, u8)) -> (u8, u8, u8) {
+    match config.color_blind {
+        Some(kind) => kind.apply(rgb, config.cvd_safe),
+        None => rgb,
+    }
+}
+
+/// Draw the gradient from 0 to 1 across `cols` columns, with value labels.
+pub(crate) fn palette_preview(
+    gradient: &Gradient<LinSrgb<f64>>,
+    painter: &mut Painter,
+    cols: usize,
+    config: &HeatmapConfig,
+) -> String {
+    let mut out = String::new();
+    let position = |j: usize| j as f64 / cols.saturating_sub(1).max(1) as f64;
+
+    for _ in 0..2 {
+        for j in 0..cols {
+            let rgb = vision(
+                config,
+                to_rgb(gradient.get(position(j)), config.clamp_to_gamut),
+            );
+            write!(out, "{} \x1b[0m", painter.bg(rgb)).unwrap();
+        }
+        writeln!(out).unwrap();
+    }
+
+    // Center each label under its column, keeping the outer ones in bounds
+    let mut labels = vec![' '; cols];
+    for k in 0..PREVIEW_LABELS {
+        let value = k as f64 / (PREVIEW_LABELS - 1) as f64;
+        let label = format!("{:.2}", value);
+        let column = (value * cols.saturating_sub(1) as f64).round() as usize;
+        let start = column
+            .saturating_sub(label.len() / 2)
+            .min(cols.saturating_sub(label.len()));
+        for (slot, ch) in labels.iter_mut().skip(start).zip(label.chars()) {
+            *slot = ch;
+        }
+    }
+    writeln!(out, "{}", labels.into_iter().collect::<String>()).unwrap();
+    out
+}
+
+/// One character cell of the banner
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct Cell<'a> {
+    pub(crate) bg: (u8, u8, u8),
+    pub(crate) fg: (u8, u8, u8),
+    pub(crate) ch: &'a str,
+}
+
+/// How far cell `i`, `j` is into the fade, from 0 (untouched) to 1 (the edge
+/// that darkens).
+fn fade_distance(direction: FadeDirection, i: usize, j: usize, rows: usize, cols: usize) -> f64 {
+    let y = i as f64 / rows as f64;
+    let x = j as f64 / cols as f64;
+    // Distance from the center, reaching 1 in the corners
+    let radial = || ((x - 0.5).powi(2) + (y - 0.5).powi(2)).sqrt() / 0.5f64.sqrt();
+    match direction {
+        FadeDirection::Left => 1.0 - x,
+        FadeDirection::Right => x,
+        FadeDirection::Top => 1.0 - y,
+        FadeDirection::Bottom => y,
+        FadeDirection::Center => 1.0 - radial(),
+        FadeDirection::Edges => radial(),
+    }
+}
+
+/// Darken row `i` of a normalized field towards the --fade-direction edge.
+///
+/// The random jitter is drawn even without a fade, so turning it on doesn't
+/// shift the random numbers used by anything after it.
+pub(crate) fn fade_row(
+    config: &HeatmapConfig,
+    i: usize,
+    row: &[f64],
+    rows: usize,
+    rng: &mut StdRng,
+) -> Vec<f64> {
+    let cols = row.len();
+    let direction = config.fade_direction.unwrap_or(FadeDirection::Right);
+    row.iter()
+        .enumerate()
+        .map(|(j, &val)| {
+            let distance = if config.bidirectional_fade {
+                // Out from the middle column to either side
+                let x = j as f64 / cols as f64;
+                (2.0 * x - 1.0).abs().powf(config.fade_steepness)
+            } else {
+                fade_distance(direction, i, j, rows, cols)
+            };
+            let fade_factor = (1.0
+                - distance
+                    * (1.0 + rng.gen_range(-config.fade_factor_range..=config.fade_factor_range)))
+            .clamp(0.0, 1.0);
+            if config.fade_direction.is_some() || config.bidirectional_fade {
+                val * fade_factor
+            } else {
+                val
+            }
+        })
+        .collect()
+}
+
+/// Where the overlay text sits in a banner of a given height
+pub(crate) struct Layout<'a> {
+    /// The figure, pushed down by blank lines when aligned
+    figlet_lines: Vec<&'a str>,
+    /// The letter under each column of the figure, see [`letter_columns`]
+    letters: Vec<Option<usize>>,
+    letter_count: usize,
+    /// Distances to the letters under --text-mask-feather, otherwise empty
+    distances: Vec<Vec<f64>>,
+    /// Number of rows actually drawn
+    pub(crate) out_rows: usize,
+}
+
+impl<'a> Layout<'a> {
+    pub(crate) fn new(config: &HeatmapConfig, rows: usize, figlet_lines: &[&'a str]) -> Self {
+        // Aligned text sits within the whole grid, pushed down by blank lines
+        let figlet_lines: Vec<&'a str> = match config.text_valign {
+            Some(valign) => {
+                let height = figlet_lines.len().saturating_sub(2);
+                let top = match valign {
+                    VerticalAlign::Top => 0,
+                    VerticalAlign::Middle => rows.saturating_sub(height) / 2,
+                    VerticalAlign::Bottom => rows.saturating_sub(height),
+                };
+                iter::repeat_n("", top)
+                    .chain(figlet_lines.iter().copied())
+                    .collect()
+            }
+            None => figlet_lines.to_vec(),
+        };
+
+        let (letters, letter_count) = letter_columns(&figlet_lines, config.glyph_threshold);
+        let distances = if config.text_mask_feather > 0 {
+            letter_distances(
+                &figlet_lines,
+                config.text_mask_feather,
+                config.glyph_threshold,
+            )
+        } else {
+            Vec::new()
+        };
+
+        // With text the banner is as tall as the figure, otherwise the whole grid
+        let has_overlay = config.text.is_some() || config.symbol.is_some();
+        let out_rows = if has_overlay && config.text_valign.is_none() {
+            let shadow_rows = if config.text_shadow {
+                config.shadow_offset.1.max(0) as usize
+            } else {
+                0
+            };
+            let extruded_rows = match config.text_3d {
+                Some(depth) if config.text_3d_direction.step().1 > 0 => depth as usize,
+                _ => 0,
+            };
+            (figlet_lines.len().saturating_sub(2) + shadow_rows.max(extruded_rows)).min(rows)
+        } else {
+            rows
+        };
+
+        Self {
+            figlet_lines,
+            letters,
+            letter_count,
+            distances,
+            out_rows,
+        }
+    }
+
+    /// Whether cell `i`, `j` of the banner is part of a letter.
+    pub(crate) fn is_letter(&self, i: usize, j: usize, threshold: f64) -> bool {
+        is_glyph(&self.figlet_lines, i as i64, j as i64, threshold)
+    }
+
+    /// Middle of the box around the letters, as a row and column, or `None`
+    /// without any.
+    pub(crate) fn text_center(&self, threshold: f64) -> Option<(f64, f64)> {
+        let filled: Vec<(usize, usize)> = self
+            .figlet_lines
+            .iter()
+            .enumerate()
+            .flat_map(|(i, line)| {
+                line.chars()
+                    .enumerate()
+                    .filter(|&(_, ch)| is_filled(ch, threshold))
+                    .map(move |(j, _)| (i, j))
+            })
+            .collect();
+        let top = filled.iter().map(|&(i, _)| i).min()?;
+        let bottom = filled.iter().map(|&(i, _)| i).max()?;
+        let left = filled.iter().map(|&(_, j)| j).min()?;
+        let right = filled.iter().map(|&(_, j)| j).max()?;
+        Some(((top + bottom) as f64 / 2.0, (left + right) as f64 / 2.0))
+    }
+
+    /// The --rainbow-text color of the letter at column `j`, spreading the
+    /// letters evenly along the gradient with a lone letter in the middle.
+    fn letter_color(
+        &self,
+        j: usize,
+        gradient: &Gradient<LinSrgb<f64>>,
+        config: &HeatmapConfig,
+    ) -> Option<(u8, u8, u8)> {
+        let k = self.letters.get(j).copied().flatten()?;
+        let position = if self.letter_count > 1 {
+            k as f64 / (self.letter_count - 1) as f64
+        } else {
+            0.5
+        };
+        Some(to_rgb(gradient.get(position), config.clamp_to_gamut))
+    }
+
+    /// Color row `i` of a faded field and overlay its row of the figure.
+    ///
+    /// Cells marked in `reversed` take their color from the far end of the
+    /// gradient instead, see --palette-reverse-per-octave.
+    pub(crate) fn row(
+        &self,
+        config: &'a HeatmapConfig,
+        i: usize,
+        heatmap_row: &[f64],
+        reversed: Option<&[bool]>,
+        ramp: &'a [String],
+        gradient: &Gradient<LinSrgb<f64>>,
+    ) -> Vec<Cell<'a>> {
+        let line = self.figlet_lines.get(i).copied().unwrap_or("");
+        let mut out_row = Vec::with_capacity(heatmap_row.len());
+        for (j, &val) in heatmap_row.iter().enumerate() {
+            let position = match reversed.and_then(|row| row.get(j)) {
+                Some(true) => 1.0 - val,
+                _ => val,
+            };
+            let (mut r, mut g, mut b) = to_rgb(gradient.get(position), config.clamp_to_gamut);
+
+            if config.high_contrast {
+                (r, g, b) = lift_to_luminance((r, g, b), HIGH_CONTRAST_LUMINANCE);
+            }
+
+            let ch = figlet_char(line, j);
+            let filled = is_filled(ch, config.glyph_threshold)
+                || (config.noise_threshold_text && val > config.threshold);
+
+            // Calculate the luminance of the background color
+            let luminance = 0.299 * r as f32 + 0.587 * g as f32 + 0.114 * b as f32;
+
+            // Determine the text color based on the luminance
+            let (text_r, text_g, text_b) = if config.high_contrast {
+                (255, 255, 255) // Glyphs are drawn over black letters
+            } else if luminance > 128.0 {
+                (0, 0, 0) // Bright background, use black text
+            } else {
+                (255, 255, 255) // Dark background, use white text
+            };
+
+            let (base_r, base_g, base_b) = (r, g, b);
+            let mut shadowed = false;
+            if filled {
+                // Masked letters keep the full heatmap color
+                if config.high_contrast {
+                    (r, g, b) = (0, 0, 0);
+                } else if let Some(color) = self
+                    .letter_color(j, gradient, config)
+                    .filter(|_| config.rainbow_text)
+                {
+                    (r, g, b) = color;
+                } else if !config.multiply_by_mask && !config.invert_text {
+                    (r, g, b) = darken((r, g, b), letter_darkening(config, val));
+                }
+            } else {
+                if config.multiply_by_mask {
+                    (r, g, b) = config.mask_outside_color;
+                } else if config.invert_text {
+                    // The letters stand out bright from a darkened banner
+                    (r, g, b) = darken((r, g, b), TEXT_DARKENING);
+                } else if let Some(&d) = self.distances.get(i).and_then(|row| row.get(j)) {
+                    // The darkening eases off with distance from the letters
+                    let radius = config.text_mask_feather as f64;
+                    if d <= radius {
+                        let strength = (1.0 - d / (radius + 1.0)) as f32;
+                        let factor = 1.0 - (1.0 - letter_darkening(config, val)) * strength;
+                        (r, g, b) = darken((r, g, b), factor);
+                    }
+                }
+
+                // Extruded copies step back from the letters, darker the
+                // further they are, over the drop shadow
+                if let Some(depth) = config.text_3d {
+                    let (sx, sy) = config.text_3d_direction.step();
+                    let step = (1..=depth as i64).find(|&k| {
+                        is_glyph(
+                            &self.figlet_lines,
+                            i as i64 - k * sy,
+                            j as i64 - k * sx,
+                            config.glyph_threshold,
+                        )
+                    });
+                    if let Some(k) = step {
+                        let factor = TEXT_DARKENING * (1.0 - k as f32 / (depth + 1) as f32);
+                        (r, g, b) = darken((base_r, base_g, base_b), factor);
+                        shadowed = true;
+                    }
+                }
+
+                // The shadow is the letters shifted by the offset, drawn
+                // underneath so the letters themselves stay on top
+                let (dx, dy) = config.shadow_offset;
+                if config.text_shadow
+                    && !shadowed
+                    && is_glyph(
+                        &self.figlet_lines,
+                        i as i64 - dy,
+                        j as i64 - dx,
+                        config.glyph_threshold,
+                    )
+                {
+                    (r, g, b) = config.shadow_color;
+                    shadowed = true;
+                }
+            }
+
+            let mut ch_out = " ";
+            let (mut text_r, mut text_g, mut text_b) = (text_r, text_g, text_b);
+
+            // At a letter edge, only part of the cell is darkened. The block
+            // hugs whichever side the rest of the letter is on.
+            if config.smooth_text && filled {
+                let eighths = (glyph_coverage(ch) * 8.0).round() as usize;
+                let filled_left =
+                    j > 0 && is_filled(figlet_char(line, j - 1), config.glyph_threshold);
+                let filled_right = is_filled(figlet_char(line, j + 1), config.glyph_threshold);
+                if eighths < 8 && filled_left && !filled_right {
+                    ch_out = PARTIAL_BLOCKS[eighths - 1];
+                    (text_r, text_g, text_b) = (r, g, b);
+                    (r, g, b) = (base_r, base_g, base_b);
+                } else if eighths < 8 && filled_right && !filled_left {
+                    ch_out = PARTIAL_BLOCKS[7 - eighths];
+                    (text_r, text_g, text_b) = (base_r, base_g, base_b);
+                }
+            }
+
+            // Texture the rest of the letter with the undarkened heatmap color
+            if config.glyph_charset && filled && ch_out == " " {
+                let ramp_index = (val * (ramp.len() - 1) as f64).round() as usize;
+                ch_out = &ramp[ramp_index];
+                (text_r, text_g, text_b) = (base_r, base_g, base_b);
+            }
+
+            // Fill the background around the letters, keeping shadows solid
+            if let Some(background) = &config.background_char {
+                if !filled && !shadowed {
+                    ch_out = background;
+                }
+            }
+
+            // Without colors, the texture has to carry the heatmap
+            if config.strip_ansi {
+                ch_out = if filled {
+                    figlet_str(line, j)
+                } else {
+                    &ramp[(val * (ramp.len() - 1) as f64).round() as usize]
+                };
+            }
+
+            if config.cell_text {
+                ch_out = DIGITS[(val * 9.0).round() as usize];
+            }
+
+            out_row.push(Cell {
+                bg: vision(config, (r, g, b)),
+                fg: vision(config, (text_r, text_g, text_b)),
+                ch: ch_out,
+            });
+        }
+        out_row
+    }
+}
+
+/// Color a normalized field and overlay the figlet text.
+pub(crate) fn cells<'a>(
+    config: &'a HeatmapConfig,
+    data: &[Vec<f64>],
+    bands: Option<&[Vec<bool>]>,
+    figlet_lines: &[&'a str],
+    ramp: &'a [String],
+    gradient: &Gradient<LinSrgb<f64>>,
+    rng: &mut StdRng,
+) -> Vec<Vec<Cell<'a>>> {
+    let rows = data.len();
+
+    // Fade every row first, so the random jitter doesn't depend on how many
+    // rows end up drawn
+    let heatmap: Vec<Vec<f64>> = data
+        .iter()
+        .enumerate()
+        .map(|(i, row)| fade_row(config, i, row, rows, rng))
+        .collect();
+
+    let layout = Layout::new(config, rows, figlet_lines);
+    heatmap
+        .iter()
+        .enumerate()
+        .take(layout.out_rows)
+        .map(|(i, row)| {
+            let reversed = bands.and_then(|bands| bands.get(i)).map(Vec::as_slice);
+            layout.row(config, i, row, reversed, ramp, gradient)
+        })
+        .collect()
+}
+
+/// Map the colors of `cells` onto the --max-colors palette, returning the
+/// palette, or nothing without the flag.
+///
+/// Backgrounds are dithered with Floyd-Steinberg error diffusion, carrying
+/// each cell's rounding error on to the cells right of and below it. Text
+/// colors are only snapped to the palette, since scattered glyph colors
+/// would be hard to read.
+pub(crate) fn quantize(config: &HeatmapConfig, cells: &mut [Vec<Cell>]) -> Vec<(u8, u8, u8)> {
+    let Some(n) = config.max_colors else {
+        return Vec::new();
+    };
+    let colors: Vec<(u8, u8, u8)> = cells
+        .iter()
+        .flatten()
+        .flat_map(|cell| [cell.bg, cell.fg])
+        .collect();
+    let palette = median_cut(&colors, n as usize);
+
+    let cols = cells.iter().map(Vec::len).max().unwrap_or(0);
+    let mut error = vec![vec![(0.0, 0.0, 0.0); cols + 2]; cells.len() + 1];
+    for i in 0..cells.len() {
+        for (j, cell) in cells[i].iter_mut().enumerate() {
+            let (er, eg, eb) = error[i][j + 1];
+            let (r, g, b) = cell.bg;
+            let wanted = (r as f64 + er, g as f64 + eg, b as f64 + eb);
+            cell.bg = nearest(wanted, &palette);
+            cell.fg = nearest(
+                (cell.fg.0 as f64, cell.fg.1 as f64, cell.fg.2 as f64),
+                &palette,
+            );
+
+            let off = (
+                wanted.0 - cell.bg.0 as f64,
+                wanted.1 - cell.bg.1 as f64,
+                wanted.2 - cell.bg.2 as f64,
+            );
+            for (y, x, weight) in [
+                (i, j + 2, 7.0),
+                (i + 1, j, 3.0),
+                (i + 1, j + 1, 5.0),
+                (i + 1, j + 2, 1.0),
+            ] {
+                let e = &mut error[y][x];
+                e.0 += off.0 * weight / 16.0;
+                e.1 += off.1 * weight / 16.0;
+                e.2 += off.2 * weight / 16.0;
+            }
+        }
+    }
+    palette
+}
+
+/// Encode cells as escape-coded text, one line per row.
+pub(crate) fn paint(cells: &[Vec<Cell>], painter: &mut Painter) -> String {
+    let mut out = String::new();
+    for row in cells {
+        for cell in row {
+            write!(
+                out,
+                "{}{}{}\x1b[0m",
+                painter.bg(cell.bg), // Background color
+                painter.fg(cell.fg), // Text color
+                cell.ch              // Character to print
+            )
+            .unwrap();
+        }
+        writeln!(out).unwrap();
+    }
+    out
+}
+
+/// Join the characters of cells into plain text, one line per row.
+pub(crate) fn plain(cells: &[Vec<Cell>]) -> String {
+    let mut out = String::new();
+    for row in cells {
+        for cell in row {
+            out.push_str(cell.ch);
+        }
+        out.push('\n');
+    }
+    out
+}
+
+/// Move every line of `frame` to an absolute position on screen, with the top
+/// left corner at `row`, `col`.
+///
+/// Each line is prefixed with a cursor move instead of ending in a newline,
+/// so nothing outside the banner is touched. The cursor is saved beforehand
+/// and restored afterwards.
+pub(crate) fn place(frame: &str, row: u16, col: u16) -> String {
+    let mut out = String::from("\x1b7");
+    for (k, line) in frame.lines().enumerate() {
+        write!(out, "\x1b[{};{}H{}", row as usize + k, col, line).unwrap();
+    }
+    out.push_str("\x1b8");
+    out
+}
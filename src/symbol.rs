@@ -0,0 +1,85 @@
+use crate::error::BannerError;
+
+/// Rows and columns in each symbol bitmap
+const BITMAP_SIZE: usize = 8;
+
+/// Built-in 8x8 bitmaps, one byte per row with the leftmost pixel in the high
+/// bit, and the characters drawn with each
+const SYMBOLS: [(&[char], [u8; BITMAP_SIZE]); 10] = [
+    (
+        &['♥', '❤', '♡', '💖'],
+        [0x66, 0xff, 0xff, 0xff, 0x7e, 0x3c, 0x18, 0x00],
+    ),
+    (
+        &['★', '⭐', '☆', '*'],
+        [0x18, 0x18, 0xff, 0x7e, 0x3c, 0x66, 0xc3, 0x00],
+    ),
+    (
+        &['☺', '🙂', '😀', '😊'],
+        [0x7e, 0x81, 0xa5, 0x81, 0xa5, 0x99, 0x81, 0x7e],
+    ),
+    (
+        &['✓', '✔', '✅'],
+        [0x01, 0x03, 0x06, 0x8c, 0xd8, 0x70, 0x20, 0x00],
+    ),
+    (
+        &['✗', '✘', '×', '❌'],
+        [0xc3, 0x66, 0x3c, 0x18, 0x3c, 0x66, 0xc3, 0x00],
+    ),
+    (
+        &['♦', '◆', '💎'],
+        [0x18, 0x3c, 0x7e, 0xff, 0x7e, 0x3c, 0x18, 0x00],
+    ),
+    (
+        &['→', '➜', '➡'],
+        [0x08, 0x0c, 0xfe, 0xff, 0xfe, 0x0c, 0x08, 0x00],
+    ),
+    (
+        &['←', '⬅'],
+        [0x10, 0x30, 0x7f, 0xff, 0x7f, 0x30, 0x10, 0x00],
+    ),
+    (
+        &['↑', '⬆'],
+        [0x18, 0x3c, 0x7e, 0xff, 0x3c, 0x3c, 0x3c, 0x00],
+    ),
+    (
+        &['↓', '⬇'],
+        [0x3c, 0x3c, 0x3c, 0xff, 0x7e, 0x3c, 0x18, 0x00],
+    ),
+];
+
+/// Draw `symbol` as a figure in the same form as figlet output, with `#` for
+/// filled cells.
+///
+/// The bitmap is scaled up in whole blocks to fill `rows`, each pixel twice as
+/// wide as it is tall so it looks square in a terminal. Only the first code
+/// point is looked up, so emoji variation selectors don't matter. Like figlet
+/// output, the figure ends with two blank rows.
+pub(crate) fn symbol_figure(symbol: &str, rows: usize) -> Result<String, BannerError> {
+    let first = symbol.chars().next().unwrap_or(' ');
+    let (_, bitmap) = SYMBOLS
+        .iter()
+        .find(|(chars, _)| chars.contains(&first))
+        .ok_or_else(|| {
+            let known: String = SYMBOLS.iter().map(|(chars, _)| chars[0]).collect();
+            BannerError::TextConvert(format!(
+                "no bitmap for symbol {:?}, available symbols: {}",
+                symbol, known
+            ))
+        })?;
+
+    let scale = (rows / BITMAP_SIZE).max(1);
+    let mut out = String::new();
+    for bits in bitmap {
+        let line: String = (0..BITMAP_SIZE)
+            .map(|x| if bits & (0x80 >> x) != 0 { "#" } else { " " })
+            .flat_map(|pixel| std::iter::repeat_n(pixel, 2 * scale))
+            .collect();
+        for _ in 0..scale {
+            out.push_str(line.trim_end());
+            out.push('\n');
+        }
+    }
+    out.push_str("\n\n");
+    Ok(out)
+}
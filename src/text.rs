@@ -0,0 +1,284 @@
+use crate::config::{Direction, HeatmapConfig, Overflow};
+use crate::error::BannerError;
+use figlet_rs::FIGfont;
+use std::fs;
+use std::path::Path;
+use unicode_segmentation::UnicodeSegmentation;
+
+/// Density ramp used to texture letters, from sparsest to densest
+pub(crate) const GLYPH_RAMP: [&str; 9] = [".", ":", "-", "=", "+", "*", "#", "%", "@"];
+
+/// Left-aligned blocks covering one to seven eighths of a cell
+pub(crate) const PARTIAL_BLOCKS: [&str; 7] = ["▏", "▎", "▍", "▌", "▋", "▊", "▉"];
+
+/// Load a density ramp from `path`, from sparsest to densest.
+///
+/// Every grapheme is one level, so entries made of several code points
+/// (emoji, combining marks) stay whole. Line breaks are ignored, letting long
+/// ramps be wrapped across lines.
+pub(crate) fn load_ramp(path: &Path) -> Result<Vec<String>, BannerError> {
+    let content = fs::read_to_string(path).map_err(|e| BannerError::read(path, e))?;
+    let ramp: Vec<String> = content
+        .lines()
+        .flat_map(|line| line.graphemes(true))
+        .map(str::to_string)
+        .collect();
+    if ramp.len() < 2 {
+        return Err(BannerError::InvalidConfig(format!(
+            "charset file {} needs at least two characters",
+            path.display()
+        )));
+    }
+    Ok(ramp)
+}
+
+/// Estimate how much of a terminal cell a figlet character fills.
+///
+/// Fonts shade the edges of their letters with lighter characters, so these
+/// act as a sub-cell sample of the letter shape.
+pub(crate) fn glyph_coverage(ch: char) -> f64 {
+    match ch {
+        ' ' => 0.0,
+        '.' | ',' | '\'' | '`' | '-' | '_' => 0.25,
+        'o' | ':' | ';' | '"' | '~' => 0.5,
+        'd' | 'b' | 'p' | 'q' | 'P' | 'Y' => 0.75,
+        _ => 1.0,
+    }
+}
+
+/// Whether a figlet character is part of a letter rather than background,
+/// i.e. covers more than `threshold` of its cell.
+pub(crate) fn is_filled(ch: char, threshold: f64) -> bool {
+    glyph_coverage(ch) > threshold
+}
+
+/// Whether the figure has a letter at row `i`, column `j`.
+///
+/// Coordinates outside the figure, including negative ones, are empty.
+pub(crate) fn is_glyph(figlet_lines: &[&str], i: i64, j: i64, threshold: f64) -> bool {
+    i >= 0
+        && j >= 0
+        && figlet_lines
+            .get(i as usize)
+            .is_some_and(|line| is_filled(figlet_char(line, j as usize), threshold))
+}
+
+/// Number every letter of the figure from left to right, returning the
+/// letter under each column (`None` between letters) and how many there are.
+///
+/// Letters are runs of columns with something drawn in them, split by
+/// columns that are empty in every row.
+pub(crate) fn letter_columns(figlet_lines: &[&str], threshold: f64) -> (Vec<Option<usize>>, usize) {
+    let width = figlet_lines
+        .iter()
+        .map(|line| line.chars().count())
+        .max()
+        .unwrap_or(0);
+    let mut columns = Vec::with_capacity(width);
+    let mut letters = 0;
+    let mut in_letter = false;
+    for j in 0..width {
+        let filled = figlet_lines
+            .iter()
+            .any(|line| is_filled(figlet_char(line, j), threshold));
+        if filled && !in_letter {
+            letters += 1;
+        }
+        in_letter = filled;
+        columns.push(filled.then(|| letters - 1));
+    }
+    (columns, letters)
+}
+
+/// The figlet character at column `j` of `line` as a slice of it, or a space
+/// past its end.
+pub(crate) fn figlet_str(line: &str, j: usize) -> &str {
+    match line.char_indices().nth(j) {
+        Some((start, ch)) => &line[start..start + ch.len_utf8()],
+        None => " ",
+    }
+}
+
+/// The figlet character at column `j` of `line`, or a space past its end.
+pub(crate) fn figlet_char(line: &str, j: usize) -> char {
+    line.chars().nth(j).unwrap_or(' ')
+}
+
+/// Render the overlay text with the embedded font.
+///
+/// Each line of the text becomes its own row of letters, stacked top to
+/// bottom. Blank lines leave a gap as tall as the font.
+pub(crate) fn figlet_text(config: &HeatmapConfig) -> Result<String, BannerError> {
+    let Some(text) = &config.text else {
+        return Ok(String::new());
+    };
+
+    let standard_font =
+        FIGfont::from_content(include_str!("../font.flf")).map_err(BannerError::FontLoad)?;
+    let mut out = String::new();
+    for line in text.split('\n') {
+        if line.trim().is_empty() {
+            let height = standard_font.header_line.height.max(0) as usize;
+            out.push_str(&"\n".repeat(height));
+        } else {
+            out.push_str(&figlet_line(&standard_font, line, config)?);
+        }
+    }
+    Ok(out)
+}
+
+/// Render one line of text as a row of figlet letters.
+fn figlet_line(font: &FIGfont, text: &str, config: &HeatmapConfig) -> Result<String, BannerError> {
+    let padding = " ".repeat(config.pad_text);
+    let text = &format!("{}{}{}", padding, text, padding);
+    let mut figure = font.convert(text).ok_or_else(|| {
+        BannerError::TextConvert(format!("the font has no characters for {:?}", text))
+    })?;
+
+    // Fonts declare right-to-left printing with a direction of 1
+    let rtl = match config.direction {
+        Direction::Auto => font.header_line.print_direction == Some(1),
+        Direction::Ltr => false,
+        Direction::Rtl => true,
+    };
+    if rtl {
+        figure.characters.reverse();
+    }
+    if config.letter_spacing == 0 {
+        return Ok(figure.to_string());
+    }
+
+    // Join the letters row by row, with blank columns between them
+    let spacing = " ".repeat(config.letter_spacing);
+    let mut out = String::new();
+    for i in 0..figure.height as usize {
+        let row: Vec<&str> = figure
+            .characters
+            .iter()
+            .filter_map(|c| c.characters.get(i).map(String::as_str))
+            .collect();
+        out.push_str(&row.join(&spacing));
+        out.push('\n');
+    }
+    Ok(out)
+}
+
+/// Put `gap` blank rows between the rows of a figure, for --line-height.
+pub(crate) fn space_rows(figure: &str, gap: usize) -> String {
+    let lines: Vec<&str> = figure.lines().collect();
+    // Figures end with two blank rows that aren't part of the letters
+    let height = lines.len().saturating_sub(2);
+    let mut out = String::new();
+    for (k, line) in lines.iter().enumerate() {
+        out.push_str(line);
+        out.push('\n');
+        if k + 1 < height {
+            out.push_str(&"\n".repeat(gap));
+        }
+    }
+    out
+}
+
+/// Cut every row of a figure to its first `columns` columns, for
+/// --animate-text-reveal.
+pub(crate) fn reveal(figure: &str, columns: usize) -> String {
+    figure
+        .lines()
+        .map(|line| {
+            let mut shown: String = line.chars().take(columns).collect();
+            shown.push('\n');
+            shown
+        })
+        .collect()
+}
+
+/// Pad the figures on the left so each is centered on the widest.
+pub(crate) fn center_figures(figures: &mut [String]) {
+    let width = |figure: &str| figure.lines().map(|l| l.chars().count()).max().unwrap_or(0);
+    let widest = figures.iter().map(|f| width(f)).max().unwrap_or(0);
+    for figure in figures.iter_mut() {
+        let padding = " ".repeat((widest - width(figure)) / 2);
+        *figure = figure
+            .lines()
+            .map(|line| {
+                if line.is_empty() {
+                    "\n".to_string()
+                } else {
+                    format!("{}{}\n", padding, line)
+                }
+            })
+            .collect();
+    }
+}
+
+/// Fit a figure taller than `rows` into the banner as `overflow` says.
+///
+/// Figures that already fit, and grids of unknown height (`rows` of 0), are
+/// returned as they are. Keeping the top rows is left to the renderer, which
+/// stops at the last row anyway. Shrinking samples every row and column at
+/// the same stride, so letters keep their proportions.
+pub(crate) fn fit_figure(figure: &str, rows: usize, overflow: Overflow) -> String {
+    let lines: Vec<&str> = figure.lines().collect();
+    // Figures end with two blank rows, which don't count towards the height
+    let height = lines.len().saturating_sub(2);
+    if rows == 0 || height <= rows {
+        return figure.to_string();
+    }
+
+    let kept: Vec<String> = match overflow {
+        Overflow::CropBottom => return figure.to_string(),
+        Overflow::CropTop => lines[height - rows..height]
+            .iter()
+            .map(|line| line.to_string())
+            .collect(),
+        Overflow::CropCenter => {
+            let top = (height - rows) / 2;
+            lines[top..top + rows]
+                .iter()
+                .map(|line| line.to_string())
+                .collect()
+        }
+        Overflow::Shrink => {
+            let width = lines.iter().map(|line| line.chars().count()).max();
+            let cols = width.unwrap_or(0) * rows / height;
+            (0..rows)
+                .map(|i| {
+                    let line = lines[i * height / rows];
+                    let shrunk: String = (0..cols)
+                        .map(|j| figlet_char(line, j * height / rows))
+                        .collect();
+                    shrunk.trim_end().to_string()
+                })
+                .collect()
+        }
+    };
+
+    let mut out = String::new();
+    for line in kept {
+        out.push_str(&line);
+        out.push('\n');
+    }
+    out.push_str("\n\n");
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::Parser;
+
+    fn figure(text: &str, args: &[&str]) -> String {
+        let config = HeatmapConfig::parse_from(["banner", "8", "-t", text].iter().chain(args));
+        figlet_text(&config).unwrap()
+    }
+
+    #[test]
+    fn rtl_places_letters_in_reverse() {
+        assert_eq!(figure("H", &["--direction", "rtl"]), figure("H", &[]));
+        assert_eq!(figure("Hi!", &["--direction", "rtl"]), figure("!iH", &[]));
+        assert_eq!(
+            figure("Hey 42", &["--direction", "rtl", "--letter-spacing", "1"]),
+            figure("24 yeH", &["--letter-spacing", "1"])
+        );
+    }
+}
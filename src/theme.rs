@@ -0,0 +1,157 @@
+use crate::color::{median_cut, parse_hex_color, relative_luminance};
+use crate::error::BannerError;
+use crate::picture;
+use palette::{Gradient, LinSrgb, Mix};
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+/// Named gradients, each a list of color stops from low to high values
+pub type Themes = HashMap<String, Vec<LinSrgb<f64>>>;
+
+/// Theme used when none is selected
+pub const DEFAULT_THEME: &str = "dracula";
+
+/// Number of stops taken from an image by [`image_stops`]
+const IMAGE_STOPS: usize = 5;
+
+/// Longest side images are shrunk to before their colors are counted
+const IMAGE_SAMPLE_SIZE: u32 = 128;
+
+/// Convert a hex color to a gradient stop.
+///
+/// Stops take the channel bytes scaled to [0, 1] as-is, matching how the
+/// built-in stops are written.
+fn stop((r, g, b): (u8, u8, u8)) -> LinSrgb<f64> {
+    LinSrgb::new(r as f64 / 255.0, g as f64 / 255.0, b as f64 / 255.0)
+}
+
+fn hex_stops(hex: &[&str]) -> Vec<LinSrgb<f64>> {
+    hex.iter()
+        .map(|h| stop(parse_hex_color(h).unwrap()))
+        .collect()
+}
+
+/// The themes that ship with banner.
+pub fn builtin() -> Themes {
+    let mut themes = Themes::new();
+    themes.insert(
+        "dracula".to_string(),
+        vec![
+            LinSrgb::new(0.157, 0.165, 0.212), // Final color #282A36
+            LinSrgb::new(0.0, 0.5, 0.7),       // Adjusted light blue
+            LinSrgb::new(0.545, 0.914, 0.992), // #8BE9FD (cyan from Dracula theme)
+            LinSrgb::new(0.7, 0.85, 0.9),      // Adjusted light cyan
+        ],
+    );
+    themes.insert(
+        "nord".to_string(),
+        hex_stops(&["#2e3440", "#3b4252", "#5e81ac", "#88c0d0", "#eceff4"]),
+    );
+    themes.insert(
+        "gruvbox".to_string(),
+        hex_stops(&["#282828", "#98971a", "#d79921", "#fe8019", "#fbf1c7"]),
+    );
+    themes
+}
+
+/// Crossfade two themes, from all `a` at `t = 0` to all `b` at `t = 1`.
+///
+/// Both are resampled to a common set of evenly spaced stops first, so stops
+/// pair up even when the themes have different numbers of them. The common
+/// spacing divides both originals', so every original stop is kept and
+/// neither gradient changes shape.
+pub fn blend(a: &[LinSrgb<f64>], b: &[LinSrgb<f64>], t: f64) -> Vec<LinSrgb<f64>> {
+    if t == 0.0 {
+        return a.to_vec();
+    }
+
+    let gcd = |mut x: usize, mut y: usize| {
+        while y != 0 {
+            (x, y) = (y, x % y);
+        }
+        x
+    };
+    let (sa, sb) = (a.len() - 1, b.len() - 1);
+    let segments = sa / gcd(sa, sb) * sb;
+
+    let (a, b) = (Gradient::new(a.to_vec()), Gradient::new(b.to_vec()));
+    (0..=segments)
+        .map(|k| {
+            let position = k as f64 / segments as f64;
+            a.get(position).mix(&b.get(position), t)
+        })
+        .collect()
+}
+
+/// Build a theme from the dominant colors of the image at `path`, picked by
+/// median cut and ordered from darkest to brightest.
+pub fn image_stops(path: &Path) -> Result<Vec<LinSrgb<f64>>, BannerError> {
+    let image = picture::open(path, "theme")?;
+    let pixels: Vec<(u8, u8, u8)> = image
+        .thumbnail(IMAGE_SAMPLE_SIZE, IMAGE_SAMPLE_SIZE)
+        .to_rgb8()
+        .pixels()
+        .map(|p| (p[0], p[1], p[2]))
+        .collect();
+    if pixels.is_empty() {
+        return Err(BannerError::InvalidConfig(format!(
+            "theme image {} has no pixels",
+            path.display()
+        )));
+    }
+
+    let mut colors = median_cut(&pixels, IMAGE_STOPS);
+    colors.sort_by(|&a, &b| relative_luminance(a).total_cmp(&relative_luminance(b)));
+    // A gradient needs two ends, even from a one-color image
+    if colors.len() == 1 {
+        colors.push(colors[0]);
+    }
+    Ok(colors.into_iter().map(stop).collect())
+}
+
+/// Load themes from a TOML or JSON file mapping names to lists of hex stops.
+///
+/// ```toml
+/// ocean = ["#001b2e", "#00629b", "#9ad9ff"]
+/// ```
+pub fn load_theme_file(path: &Path) -> Result<Themes, BannerError> {
+    let content = fs::read_to_string(path).map_err(|e| BannerError::read(path, e))?;
+
+    let is_json = path
+        .extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("json"));
+    let raw: HashMap<String, Vec<String>> = if is_json {
+        serde_json::from_str(&content).map_err(|e| e.to_string())
+    } else {
+        toml::from_str(&content).map_err(|e| e.to_string())
+    }
+    .map_err(|e| {
+        BannerError::InvalidConfig(format!("invalid theme file {}: {}", path.display(), e))
+    })?;
+
+    raw.into_iter()
+        .map(|(name, hex)| {
+            if hex.len() < 2 {
+                return Err(BannerError::InvalidConfig(format!(
+                    "theme {:?} in {} needs at least two colors",
+                    name,
+                    path.display()
+                )));
+            }
+            let stops = hex
+                .iter()
+                .map(|h| parse_hex_color(h).map(stop))
+                .collect::<Result<_, _>>()
+                .map_err(|e| {
+                    BannerError::InvalidConfig(format!(
+                        "theme {:?} in {}: {}",
+                        name,
+                        path.display(),
+                        e
+                    ))
+                })?;
+            Ok((name, stops))
+        })
+        .collect()
+}
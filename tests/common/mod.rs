@@ -0,0 +1,19 @@
+//! Helpers shared by the integration tests
+
+use std::process::Command;
+
+/// Run the banner binary with `args` and return what it printed, failing the
+/// test if it exits with an error.
+pub fn banner(args: &[&str]) -> String {
+    let out = Command::new(env!("CARGO_BIN_EXE_banner"))
+        .args(args)
+        .output()
+        .expect("banner should run");
+    assert!(
+        out.status.success(),
+        "banner {:?} failed: {}",
+        args,
+        String::from_utf8_lossy(&out.stderr)
+    );
+    String::from_utf8(out.stdout).expect("banner should print UTF-8")
+}
@@ -0,0 +1,88 @@
+//! Render known text and check that every letter cell lands exactly under
+//! the column figlet draws it in, however the text is placed.
+
+mod common;
+
+use common::banner;
+use figlet_rs::FIGfont;
+
+/// Characters --strip-ansi draws the background with
+const RAMP: &str = ".:-=+*#%@";
+
+const ROWS: usize = 12;
+const WIDTH: usize = 40;
+
+/// The rows of letters figlet draws for `text` in the bundled font, without
+/// the two blank rows every figure ends with.
+fn figure(text: &str) -> Vec<Vec<char>> {
+    let font = FIGfont::from_file("font.flf").unwrap();
+    let figure = font.convert(text).unwrap().to_string();
+    let lines: Vec<Vec<char>> = figure.lines().map(|l| l.chars().collect()).collect();
+    lines[..lines.len() - 2].to_vec()
+}
+
+/// Render `text` with `args` and check the letters of `expected` start on
+/// row `top`, with only background around them.
+fn check_alignment(text: &str, args: &[&str], expected: &[Vec<char>], top: usize) {
+    let (rows, width) = (ROWS.to_string(), WIDTH.to_string());
+    let mut all = vec![
+        rows.as_str(),
+        "-t",
+        text,
+        "-w",
+        &width,
+        "-r",
+        "1",
+        "--strip-ansi",
+    ];
+    all.extend(args);
+    let out = banner(&all);
+    let lines: Vec<Vec<char>> = out.lines().map(|l| l.chars().collect()).collect();
+
+    for (i, line) in lines.iter().enumerate() {
+        assert_eq!(line.len(), WIDTH, "row {} of {:?}", i, args);
+        let letters = i.checked_sub(top).and_then(|k| expected.get(k));
+        for (j, &ch) in line.iter().enumerate() {
+            match letters.and_then(|row| row.get(j)) {
+                Some(&glyph) if glyph != ' ' => {
+                    assert_eq!(ch, glyph, "cell {}, {} of {:?}", i, j, args)
+                }
+                _ => assert!(
+                    RAMP.contains(ch),
+                    "cell {}, {} of {:?} should be background, got {:?}",
+                    i,
+                    j,
+                    args,
+                    ch
+                ),
+            }
+        }
+    }
+}
+
+#[test]
+fn letters_fill_the_banner_without_alignment() {
+    let expected = figure("Hi!");
+    let out = banner(&["12", "-t", "Hi!", "-w", "40", "--strip-ansi"]);
+    assert_eq!(out.lines().count(), expected.len());
+    check_alignment("Hi!", &[], &expected, 0);
+}
+
+#[test]
+fn letters_follow_each_vertical_alignment() {
+    let expected = figure("Hi!");
+    let height = expected.len();
+    for (valign, top) in [
+        ("top", 0),
+        ("middle", (ROWS - height) / 2),
+        ("bottom", ROWS - height),
+    ] {
+        check_alignment("Hi!", &["--text-valign", valign], &expected, top);
+    }
+}
+
+#[test]
+fn padding_moves_the_letters_right() {
+    let expected = figure("  Hi!  ");
+    check_alignment("Hi!", &["--pad-text", "2"], &expected, 0);
+}
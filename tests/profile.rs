@@ -0,0 +1,35 @@
+//! Flags given on the command line layer on top of a --profile.
+
+mod common;
+
+use common::banner;
+use std::fs;
+
+#[test]
+fn overlay_flags_layer_on_a_profile() {
+    let path = std::env::temp_dir().join(format!("banner-profile-{}.toml", std::process::id()));
+    fs::write(&path, "[header]\nrows = 8\nwidth = 30\ntext = \"Hi\"\n").unwrap();
+    let profile_file = path.to_str().unwrap();
+
+    let layered = banner(&[
+        "--profile",
+        "header",
+        "--profile-file",
+        profile_file,
+        "--pad-text",
+        "1",
+        "--strip-ansi",
+    ]);
+    fs::remove_file(&path).unwrap();
+    let direct = banner(&[
+        "8",
+        "-w",
+        "30",
+        "-t",
+        "Hi",
+        "--pad-text",
+        "1",
+        "--strip-ansi",
+    ]);
+    assert_eq!(layered, direct);
+}
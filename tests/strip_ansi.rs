@@ -0,0 +1,23 @@
+//! --strip-ansi output must be plain text, whatever else is drawn.
+
+mod common;
+
+use common::banner;
+
+#[test]
+fn strip_ansi_prints_no_escapes() {
+    let cases: [&[&str]; 5] = [
+        &[],
+        &["--text-3d", "2"],
+        &["--text-shadow"],
+        &["--cell-text"],
+        &["--text-3d", "2", "--text-shadow", "--reproducible-colors"],
+    ];
+    for extra in cases {
+        let mut args = vec!["8", "-t", "Hi", "-w", "30", "--strip-ansi"];
+        args.extend(extra);
+        let out = banner(&args);
+        assert!(!out.is_empty(), "{:?} printed nothing", extra);
+        assert!(!out.contains('\x1b'), "{:?} printed an escape", extra);
+    }
+}
@@ -0,0 +1,35 @@
+//! --transpose turns the field on its side without changing the banner's
+//! size.
+
+mod common;
+
+use common::banner;
+use unicode_segmentation::UnicodeSegmentation;
+
+/// Rows and graphemes per row of plain output
+fn size(out: &str) -> (usize, Vec<usize>) {
+    let widths = out.lines().map(|l| l.graphemes(true).count()).collect();
+    (out.lines().count(), widths)
+}
+
+#[test]
+fn transpose_keeps_rows_by_cols() {
+    let cases: [&[&str]; 2] = [
+        &["5", "-w", "17"],
+        &["9", "-w", "31", "-t", "Hi", "--text-valign", "top"],
+    ];
+    for base in cases {
+        let rows: usize = base[0].parse().unwrap();
+        let cols: usize = base[2].parse().unwrap();
+        let mut plain = base.to_vec();
+        plain.extend(["-r", "3", "--strip-ansi"]);
+        let mut transposed = plain.clone();
+        transposed.push("--transpose");
+
+        let (plain, transposed) = (banner(&plain), banner(&transposed));
+        assert_ne!(plain, transposed);
+        for out in [plain, transposed] {
+            assert_eq!(size(&out), (rows, vec![cols; rows]), "{:?}", base);
+        }
+    }
+}